@@ -0,0 +1,24 @@
+// scene.rs puts #version, the samplers and the uniforms of post.glsl in front of this file
+//
+// One direction of the bloom blur, run across then down
+// uniform sampler2D source;      // the scene on the bright pass, the first blur after it
+// blur = ( x step, y step, 1 on the bright pass, 0 )
+// bloom = ( threshold, intensity, 0, 0 )
+
+in vec4 gl_FragCoord;
+out vec4 fragColor;
+
+const float weights[5] = float[5]( 0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216 );
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy/vec2(textureSize(source,0));
+    vec3 sum = vec3(0.0);
+    for( int i = -4; i <= 4; i++ ) {
+        vec3 c = texture( source, uv + float(i)*blur.xy ).rgb;
+        // keep what is over the threshold, the specular highlights and the glow of the spheres
+        if( blur.z > 0.0 ) c = max( c-bloom.x, 0.0 );
+        sum += c*weights[ abs(i) ];
+    }
+    fragColor = vec4( sum, 1.0 );
+}
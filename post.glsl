@@ -0,0 +1,82 @@
+// scene.rs puts #version, the samplers and the uniforms below in front of this file
+//
+// uniform sampler2D frame;        // the scene this frame
+// uniform sampler2D previous;     // the last frame of the shot before the cut
+// uniform sampler2D bloom_map;    // the blurred highlights of frame, from bloom.glsl
+// uniform sampler2D lut;          // 16x16x16 colour grade, the blue slices side by side
+// frame, previous and bloom_map are linear HDR colour
+// transition = ( kind, 0..1 of the way through it, 0, 0 ), kind is post::Transition
+// tonemap = ( operator, exposure in stops, 0, 0 ), operator is post::Tonemap
+// bloom = ( threshold, intensity, 0, 0 )
+// vignette = ( strength, inner radius, outer radius, 0 )
+// grain = ( amount, time, 0, 0 )
+// grade = ( amount of the graded colour, 0, 0, 0 )
+// uniform vec4 transition, tonemap, bloom, blur, vignette, grain, grade;
+
+in vec4 gl_FragCoord;
+out vec4 fragColor;
+
+// HDR colour to display colour
+vec3 tonemapped( vec3 c )
+{
+    c *= exp2( tonemap.y );
+    float op = tonemap.x;
+    if( op == 1.0 ) {
+        // Reinhard
+        c = c/( 1.0+c );
+    } else if( op == 2.0 ) {
+        // ACES, fit by Narkowicz
+        c = clamp( c*( 2.51*c+0.03 )/( c*( 2.43*c+0.59 )+0.14 ), 0.0, 1.0 );
+    } else if( op == 3.0 ) {
+        // filmic, Hable's curve with the white point at 11.2
+        vec3 x = c*2.0;
+        c = ( ( x*( 0.15*x+0.05 )+0.004 )/( x*( 0.15*x+0.5 )+0.06 )-0.0667 )/0.7251;
+    }
+    return pow( clamp( c, 0.0, 1.0 ), vec3( 1.0/2.2 ) );
+}
+
+vec3 lut_slice( vec3 c, float slice )
+{
+    return texture( lut, vec2( slice*16.0 + c.r + 0.5, c.g + 0.5 )/vec2( 256.0, 16.0 ) ).rgb;
+}
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy/vec2(textureSize(frame,0));
+    vec3 current = tonemapped( texture(frame,uv).rgb + texture(bloom_map,uv).rgb*bloom.y );
+    vec3 before = tonemapped( texture(previous,uv).rgb );
+    float t = transition.y;
+    float kind = transition.x;
+
+    vec3 col = current;
+    if( kind == 1.0 ) {
+        // crossfade
+        col = mix( before, current, t );
+    } else if( kind == 2.0 ) {
+        // fade out the old shot then fade in the new one
+        col = t < 0.5 ? before*(1.0-2.0*t) : current*(2.0*t-1.0);
+    } else if( kind == 3.0 ) {
+        // flash to white
+        col = mix( current, vec3(1.0), 1.0-t );
+    } else if( kind == 4.0 ) {
+        // soft edge sweeping left to right
+        float edge = t*1.1;
+        col = mix( current, before, smoothstep( edge-0.1, edge, uv.x ) );
+    }
+
+    // colour grade, blending the two nearest blue slices
+    vec3 c = clamp( col, 0.0, 1.0 )*15.0;
+    float slice = floor( c.b );
+    vec3 graded = mix( lut_slice( c, slice ), lut_slice( c, min( slice+1.0, 15.0 ) ), c.b-slice );
+    col = mix( col, graded, grade.x );
+
+    // darker towards the corners
+    float d = length( ( uv-0.5 )*vec2( 1.7778, 1.0 ) );
+    col *= 1.0 - vignette.x*smoothstep( vignette.y, vignette.z, d );
+
+    // film grain, new every frame
+    float noise = fract( sin( dot( gl_FragCoord.xy, vec2( 12.9898, 78.233 ) ) + grain.y*7.31 )*43758.5453 );
+    col += ( noise-0.5 )*grain.x;
+
+    fragColor = vec4( col, 1.0 );
+}
@@ -1,11 +1,20 @@
-#version 330 core
-const int num_spheres = 80;
+// scene.rs puts #version, sp and the uniforms below in front of this file
+//
+// Two texels per sphere, only the first num_spheres are set
+// uniform sampler2D spheres;
+// uniform int num_spheres;
+// vec4 sp( int i );            // texel i of spheres
+// cut_info = ( frames to the next event, frames since the last camera cut, time, 0 )
+// audio_info = ( audio rms, note-on pulse, started instruments, 0 ), audio_bands = audio band magnitudes
+// terrain_window = ( min x, min z, max x, max z ) of the terrain cells held by the terrain texture
+// cam_rot = ( pitch, yaw, roll, vertical field of view )
+// uniform vec4 cam_pos, cam_rot, cut_info, audio_info, audio_bands, terrain_window;
+
 const float width = 1920;
 const float height = 1080;
 //const float width = 1280;
 //const float height = 720;
 
-uniform vec4 sp[(num_spheres+2)*2];
 uniform sampler2D terrain;
 in vec4 gl_FragCoord;
 out vec4 fragColor;
@@ -19,14 +28,18 @@ vec2 water[4] = vec2[4]( normalize(vec2( 0.23, 0.65  )),
                         normalize(vec2( 0.13, -0.83  )),
                         normalize(vec2( -0.2, 0.55  )));
 
-
+// Terrain top colours for the materials written by the terrain generator
+vec3 materials[4] = vec3[4]( vec3( 0.2, 0.071, .01 ),      // rock
+                            vec3( 0.3, 0.19, 0.08 ),        // sand
+                            vec3( 0.07, 0.1, 0.02 ),        // grass
+                            vec3( 0.55, 0.55, 0.6 ) );      // snow
 
 vec3 water_ripple( vec3 pos ) {
     float intensity = 0.0;
     float intensity2 = 0.0;
     for( int k=0; k< 4; k++ ) {
         float t = pos.x*water[k].x + pos.z*water[k].y;
-        t = t*(4.0-(float(k)*0.51013))+sp[162].z;
+        t = t*(4.0-(float(k)*0.51013))+cut_info.z;
 
         float mt = 1.0/(float(k)+1.0);
         intensity += mt*sin(t-0.3*cos(t));
@@ -57,21 +70,27 @@ bool w_intersect_sphere( vec3 ray_dir, vec3 origin, vec3 sphere, float sphere_ra
     }
 }
 
-float get_height( vec2 pos, out float type ) {
-    vec4 col = texture( terrain, pos/512.0  );
-    type = col.y;
+// type.x = material ( g channel, material index / 3 ), type.y = how much the slope turns it to rock ( b channel )
+float get_height( vec2 pos, out vec2 type ) {
+    vec4 col = texture( terrain, pos/vec2( textureSize( terrain, 0 ) ) );
+    type = col.yz;
     return col.x*60.0-12.1;
 }
 
 void intersect_box( vec3 origin, vec3 delta, out float near, out float far ) {
-    vec3 t1 = (vec3( 0. ) - origin )/delta;
-    vec3 t2 = (vec3( 512. ) - origin )/delta;
+    vec3 t1 = (terrain_window.xyy - origin )/delta;
+    vec3 t2 = (terrain_window.zww - origin )/delta;
     near = max( min( t1.x, t2.x ), min( t1.z, t2.z ) );
     far = min( max( t1.x, t2.x ), max( t1.z, t2.z ) );
 }
 
-bool cast_ray( vec3 origin, vec3 delta, out float t, out vec3 col, out vec3 normal, out float refrac, out float type ) 
+bool cast_ray( vec3 origin, vec3 delta, out float t, out vec3 col, out vec3 normal, out float refrac, out vec2 type ) 
 {
+    // The world is 512 units across, step through it in terrain cells. Scaling delta the same way keeps t unchanged
+    float cells = float( textureSize( terrain, 0 ).x )/512.0;
+    origin.xz *= cells;
+    delta.xz *= cells;
+
     float near_t, far_t;
     intersect_box( origin,delta, near_t, far_t );
 
@@ -106,7 +125,7 @@ bool cast_ray( vec3 origin, vec3 delta, out float t, out vec3 col, out vec3 norm
 
         // check exit height
         if( old_height > y ) {
-            col = vec3( 0.2, 0.071, .01 ) + step( 27, old_height ) * vec3( 0.1, -0.06, 0 );
+            col = mix( materials[ int( type.x*3.0+0.5 ) ], materials[ 0 ], type.y );
             normal = vec3( 0, 1.0, 0 );
 
             // work out precise t ( maybe precision errors when delta.y is near 0)
@@ -120,13 +139,6 @@ bool cast_ray( vec3 origin, vec3 delta, out float t, out vec3 col, out vec3 norm
         if( old_height > y ) {
             refrac =1.5;
             col = vec3( .2, .2, .2 ) + step( 40, old_height ) * vec3( 0., -.03, -.1 );
-
-            if( type == 1.0 ) {
-                float threshold = (0.6335-0.01)*60.0 - 12.1;
-                if( y < threshold ) {
-                    type = 0.0;
-                }
-            }
             //t += skip_t;
             normal = vec3( -grid_step.x*or.x, 0, -grid_step.y*or.y );
             return true;
@@ -176,23 +188,20 @@ void main()
     // establish the 3d normalized 3d position, camera is at 0,0,0,   ray is towards screen_pos, depth
 //    vec3 camera_tgt_3d = vec3( screen_pos_2d, -2.0 );
     //vec3 camera_pos_3d = vec3( 0., 0., 0.);       // no need to track as it is at 0,0,0
-    vec4 co = cos( sp[ 161 ] );         // a= co.y   c = co.x
-    vec4 si = sin( sp[ 161 ] );         // b= si.y   d = si.x
+    vec4 co = cos( cam_rot );         // a= co.y   c = co.x
+    vec4 si = sin( cam_rot );         // b= si.y   d = si.x
     mat3 rot_m = mat3(  co.y,      0,     -si.y,
                         -si.x*si.y,   co.x,      -si.x*co.y,
                         co.x*si.y,    si.x,    co.y*co.x );
-    // no roll at the moment
-//     float _angle3 = sp[ 161 ].z;
-//     mat3 roll = mat3(  cos(_angle3),     -sin( _angle3) ,       0 , 
-//                         sin(_angle3),     cos(_angle3),0 ,
-//                         0,     0, 1 );
-//    rot_m = rot_m * tilt_m * roll;
+    // roll is turning the screen around its centre
+    screen_pos_2d = mat2( co.z, si.z, -si.z, co.z )*screen_pos_2d;
     
     // vec3 origin = rot_m*camera_pos_3d; no need to rotate origin, Its at 0,0,0
-    vec3 dest = rot_m*vec3( screen_pos_2d, -2.0 );
+    // cam_rot.w is the vertical field of view
+    vec3 dest = rot_m*vec3( screen_pos_2d, -1.0/tan( cam_rot.w*0.5 ) );
 //    vec3 dest = rot_m*camera_tgt_3d;
 
-    vec3 origin = sp[160].xyz;      // camera at translated origin
+    vec3 origin = cam_pos.xyz;      // camera at translated origin
     dest += origin;
 
     vec3 ray_dir = normalize( dest - origin );
@@ -207,18 +216,21 @@ void main()
         vec3 diffuseCol;
         float refractive_index;
         float reflectance = 0.0;
+        float emissive = 0.0;
         float current_t = maximum_dist;
 
         for( int idx=0; idx < num_spheres; idx++ ) {
             float n_t;          // For some reason I cant pass current_t as out var into the func. Somehow the compiler seems to optimize out
                                 // the preceeding assignment if I do
-            if( w_intersect_sphere( ray_dir, origin, sp[idx*2].xyz, sp[idx*2].w, n_t) ) {
+            if( w_intersect_sphere( ray_dir, origin, sp(idx*2).xyz, sp(idx*2).w, n_t) ) {
                 if( n_t < current_t ) {
                     current_t = n_t;
                     pos = origin + current_t*ray_dir;
-                    norm = normalize( pos-sp[idx*2].xyz);
-                    diffuseCol = sp[idx*2+1].xyz;  // vec3( 0.02, .02, 0.02 );
-                    refractive_index =  sp[idx*2+1].w;       // 1.3171;
+                    norm = normalize( pos-sp(idx*2).xyz);
+                    diffuseCol = sp(idx*2+1).xyz;  // vec3( 0.02, .02, 0.02 );
+                    // refractive index below 4, emissive*255 packed into the multiples of 4 above it
+                    refractive_index = mod( sp(idx*2+1).w, 4.0 );
+                    emissive = floor( sp(idx*2+1).w/4.0 )/255.0;
                     reflectance = fresnel( refractive_index, norm, ray_dir);
                 }
             }
@@ -228,7 +240,7 @@ void main()
         float grid_t;
         vec3 diffuseCol2;
         vec3 norm2;
-        float type;
+        vec2 type;
         if( cast_ray(origin, ray_dir, grid_t, diffuseCol2, norm2, refractive_index, type ) ) {
             // hit the scenery, if it is closer than the sphere this overrides
             if( grid_t < current_t ) {
@@ -237,6 +249,7 @@ void main()
                 norm = norm2;
                 pos = origin + ray_dir * current_t*0.9999;
                 reflectance = fresnel( refractive_index, norm, ray_dir);
+                emissive = 0.0;
             }
         } else if( ray_dir.y < 0.0 && current_t == maximum_dist) {
             current_t = ( -10.5-origin.y ) /  ray_dir.y;//   ground_plane_intersect( ray_dir, origin , -0.5 );
@@ -253,7 +266,8 @@ void main()
 
             //bend and rethrow ray underwater
             vec3 uw_dir = refract( ray_dir, norm, 1.-reflectance);
-            diffuseCol = vec3( 0.05, 0.05, 0.15 );                
+            diffuseCol = vec3( 0.05, 0.05, 0.15 );
+            emissive = 0.0;
             if( cast_ray(pos, uw_dir*100, grid_t, diffuseCol2, norm2, refractive_index, type ) ) {
                 diffuseCol += diffuseCol2 * exp( -grid_t*40.0 );
             }
@@ -272,7 +286,7 @@ void main()
         {
             for( int idx=0; idx < num_spheres; idx++ ) 
             {
-                if( w_intersect_sphere( sun_dir, pos, sp[idx*2].xyz, sp[idx*2].w, grid_t ) )  {
+                if( w_intersect_sphere( sun_dir, pos, sp(idx*2).xyz, sp(idx*2).w, grid_t ) )  {
                     in_shade = true;
                     break;
                 }
@@ -282,7 +296,8 @@ void main()
         // reusing diffusecol2 for poitn col to avoid declaring extra var
         if( !in_shade)
         {
-            float diffuse = dot( sun_dir, norm );
+            // sunlight pulses with the music
+            float diffuse = dot( sun_dir, norm )*( 1.0 + audio_info.y*0.2 );
             vec3 halfway = normalize( sun_dir-ray_dir );        // halfwar between vectors pointing towards camera and sun
             float specular = pow( dot( norm, halfway ), 121.0 );
             specular = clamp( specular, 0.0, 1.0 );
@@ -290,6 +305,8 @@ void main()
         } else {
             diffuseCol2 = diffuseCol* 0.02;
         }
+        // glowing spheres light themselves
+        diffuseCol2 += diffuseCol*emissive;
         // attenuate
         diffuseCol2 *= extinction( current_t );
         diffuseCol2 += in_scatter( current_t, dot( sun_dir,ray_dir) );
@@ -302,7 +319,7 @@ void main()
     }
 
     // vignetting
-    // float cut_fraction = min( sp[162].x, sp[162].y );
+    // float cut_fraction = min( cut_info.x, cut_info.y );
     // if( cut_fraction <= 18 ) {
     //     cut_fraction = 1.-cut_fraction/8.;
     // } else {
@@ -315,5 +332,6 @@ void main()
     // vec3 vfcolor = mix( final_color, vec3(0), vignetting_level );
 
     // //fragColor = vec4( pow( vfcolor, vec3(1.0 / 2.2) ), 1. );
-    fragColor = vec4( pow( final_color, vec3(1.0 / 2.2) ), 1. );
+    // linear and unclamped, post.glsl tonemaps it and applies the gamma
+    fragColor = vec4( final_color, 1. );
 }
@@ -0,0 +1,104 @@
+// Keyframed camera moves. A track is a list of poses at given times, started from the SEQUENCE with
+// MODE_CAM_TRACK and played back in place of the seeded pans of setup_camera
+use super::math_util;
+
+pub struct Key {
+    // seconds from the start of the track
+    pub time : f32,
+    pub pos : [ f32; 3 ],
+    // pitch, yaw, roll in the order of cam_rot
+    pub rot : [ f32; 3 ],
+    // vertical field of view in radians
+    pub fov : f32,
+}
+
+pub enum Interpolation {
+    // goes through every key
+    CatmullRom,
+    // keys come as start, control, control, end, control, control, end... The times of the control keys are
+    // not used, the curve only goes through the start and end keys
+    Bezier,
+}
+
+pub struct Track {
+    pub interpolation : Interpolation,
+    pub keys : &'static [ Key ],
+}
+
+// The field of view the shader had before it could be changed, focal length 2
+pub const DEFAULT_FOV : f32 = 0.9273;
+
+// Tracks by the number used in the SEQUENCE
+pub static TRACKS : &[ Track ] = &[
+    // low sweep along the valley, turning to look back across it
+    Track{ interpolation: Interpolation::CatmullRom, keys: &[
+        Key{ time: 0.0, pos: [ 120.0, 42.0, 140.0 ], rot: [ 0.15, 0.6, 0.0 ], fov: DEFAULT_FOV },
+        Key{ time: 4.0, pos: [ 180.0, 36.0, 200.0 ], rot: [ 0.1, 1.2, 0.05 ], fov: DEFAULT_FOV },
+        Key{ time: 8.0, pos: [ 250.0, 40.0, 230.0 ], rot: [ 0.2, 2.2, 0.0 ], fov: 1.1 },
+        Key{ time: 12.0, pos: [ 300.0, 55.0, 215.0 ], rot: [ 0.35, 3.1, -0.05 ], fov: 1.2 },
+    ] },
+    // crane up from the water, zooming in on the end
+    Track{ interpolation: Interpolation::Bezier, keys: &[
+        Key{ time: 0.0, pos: [ 260.0, 2.0, 260.0 ], rot: [ -0.1, 0.0, 0.0 ], fov: DEFAULT_FOV },
+        Key{ time: 0.0, pos: [ 262.0, 20.0, 250.0 ], rot: [ 0.0, 0.2, 0.0 ], fov: DEFAULT_FOV },
+        Key{ time: 0.0, pos: [ 270.0, 60.0, 235.0 ], rot: [ 0.4, 0.5, 0.0 ], fov: 0.8 },
+        Key{ time: 10.0, pos: [ 280.0, 70.0, 230.0 ], rot: [ 0.5, 0.6, 0.0 ], fov: 0.5 },
+    ] },
+];
+
+// A track needs a key and a Bezier track 3k+1 of them, the logger build checks TRACKS with this. sample copes
+// without but drops keys
+#[cfg(feature = "logger")]
+pub fn valid( track : &Track ) -> bool {
+    match track.interpolation {
+        Interpolation::CatmullRom => track.keys.len() > 0,
+        Interpolation::Bezier => track.keys.len() >= 4 && ( track.keys.len()-1 )%3 == 0,
+    }
+}
+
+fn channels( key : &Key ) -> [ f32; 7 ] {
+    [ key.pos[ 0 ], key.pos[ 1 ], key.pos[ 2 ], key.rot[ 0 ], key.rot[ 1 ], key.rot[ 2 ], key.fov ]
+}
+
+// Pose t seconds into the track. Holds the last key after the end. Writes the position to pos and
+// ( pitch, yaw, roll, fov ) to rot. Keys after the last complete Bezier segment are left out and a track
+// without a complete segment holds its first key
+pub fn sample( track : &Track, t : f32, pos : &mut [ f32; 4 ], rot : &mut [ f32; 4 ] ) {
+    let keys = track.keys;
+    if keys.len() == 0 {
+        return;
+    }
+    let last = keys.len()-1;
+    let ( step, end ) = match track.interpolation { Interpolation::CatmullRom => ( 1, last ), Interpolation::Bezier => ( 3, last-last%3 ) };
+
+    // segment from key i to key i+step holding t
+    let mut i = 0;
+    while i+step < end && keys[ i+step ].time <= t {
+        i += step;
+    }
+    let mut u = if end == 0 { 0.0 } else { ( t-keys[ i ].time )/( keys[ i+step ].time-keys[ i ].time ) };
+    if u < 0.0 { u = 0.0; }
+    if u > 1.0 { u = 1.0; }
+
+    let ( p0, p1, p2, p3 ) = match track.interpolation {
+        Interpolation::CatmullRom => (
+            channels( &keys[ if i > 0 { i-1 } else { 0 } ] ),
+            channels( &keys[ i ] ),
+            channels( &keys[ if i < last { i+1 } else { last } ] ),
+            channels( &keys[ if i+2 < last { i+2 } else { last } ] ) ),
+        Interpolation::Bezier => (
+            channels( &keys[ i ] ),
+            channels( &keys[ if i+1 < last { i+1 } else { last } ] ),
+            channels( &keys[ if i+2 < last { i+2 } else { last } ] ),
+            channels( &keys[ if i+3 < last { i+3 } else { last } ] ) ),
+    };
+    let mut c = 0;
+    while c < 7 {
+        let v = match track.interpolation {
+            Interpolation::CatmullRom => math_util::catmull_rom( p0[ c ], p1[ c ], p2[ c ], p3[ c ], u ),
+            Interpolation::Bezier => math_util::bezier( p0[ c ], p1[ c ], p2[ c ], p3[ c ], u ),
+        };
+        if c < 3 { pos[ c ] = v; } else { rot[ c-3 ] = v; }
+        c += 1;
+    }
+}
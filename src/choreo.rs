@@ -0,0 +1,333 @@
+// Sphere choreography. The spheres are split into GROUPS groups and each group follows its own behaviour,
+// switched from the SEQUENCE. Movement is stepped once per frame like the camera
+use super::math_util;
+use super::random;
+use super::physics;
+use super::intro::{num_spheres, MAX_SPHERES};
+
+pub const GROUPS : usize = 4;
+
+// First sphere of group g. Group g is the spheres from group_first( g ) up to group_first( g+1 ) so
+// any sphere count splits up
+pub fn group_first( g : usize ) -> usize {
+    unsafe{ g*num_spheres/GROUPS }
+}
+
+fn group_size( g : usize ) -> usize {
+    group_first( g+1 ) - group_first( g )
+}
+
+// Height of the terrain surface in world units at world x,z
+pub type Ground = fn( f32, f32 ) -> f32;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Behaviour {
+    // Rest on the terrain and bounce up to the sphere scale. The original motion
+    Bounce,
+    // Follow the leader around a closed loop through the group's control points
+    Path,
+    // Steer together as a flock chasing a point that travels the group's loop
+    Flock,
+    // Circle around the first corner of the group's loop at different radii and speeds
+    Orbit,
+    // Get thrown up then fall under gravity, bounce off and roll down the heightfield until they settle
+    Fall,
+}
+
+// Behaviours by the number used in the SEQUENCE
+pub static BEHAVIOURS : [ Behaviour; 5 ] = [ Behaviour::Bounce, Behaviour::Path, Behaviour::Flock, Behaviour::Orbit, Behaviour::Fall ];
+
+#[derive(Clone, Copy)]
+struct Group {
+    behaviour : Behaviour,
+    // time the behaviour was selected
+    start : f32,
+    // closed loop for Path and Flock, picked around where the group rests
+    points : [ [ f32; 4 ]; 4 ],
+}
+
+// Seconds to blend from where the spheres are into a new kinematic behaviour
+const BLEND_TIME : f32 = 2.0;
+// Control points per second along the loop
+const PATH_SPEED : f32 = 0.08;
+// Distance along the loop ( in control points ) between the spheres following it
+const PATH_SPACING : f32 = 0.06;
+const ORBIT_SPEED : f32 = 0.4;
+// World units per frame
+const FLOCK_SPEED : f32 = 0.6;
+
+static mut rng : random::Rng = random::Rng{seed: core::num::Wrapping(5501231)};
+
+static mut groups : [ Group; GROUPS ] = [ Group{ behaviour: Behaviour::Bounce, start: 0.0, points: [ [ 0.0; 4 ]; 4 ] }; GROUPS ];
+// Resting place of each sphere ( x, unused, z, how much further it bounces ). The height depends on the radius
+static mut home : [ [ f32; 4 ]; MAX_SPHERES ] = [ [ 0.0; 4 ]; MAX_SPHERES ];
+// World units per frame
+static mut velocity : [ [ f32; 4 ]; MAX_SPHERES ] = [ [ 0.0; 4 ]; MAX_SPHERES ];
+
+static mut bounce_start : f32 = 0.0;
+static mut bounce_scale : f32 = 0.0;
+
+// Pick the resting places on the higher ground and put the spheres there. spheres is the sphere part
+// of the world, two vec4s per sphere with the radius squared already in the w of the first
+pub fn init( spheres : &mut [ [ f32; 4 ] ], ground : Ground ) {
+    let mut rng_terrain : random::Rng = random::Rng{seed: core::num::Wrapping(7923129)};
+    unsafe{
+        let mut idx = 0;
+        loop {
+            // spheres that needed more tries bounce further. Keeps the look of the original per frame search
+            let mut lift = 1.0;
+            loop{
+                let x = rng_terrain.next_f32()*512.0;
+                let z = rng_terrain.next_f32()*512.0;
+                rng_terrain.next_f32();
+                let y = ground( x, z );
+                if y > 0.3*60.0-12.1 {
+                    home[ idx ] = [ x, 0.0, z, lift ];
+                    break;
+                }
+                lift *= 1.003;
+            }
+            spheres[ idx*2 ][ 0 ] = home[ idx ][ 0 ];
+            spheres[ idx*2 ][ 1 ] = physics::rest_height( home[ idx ][ 0 ], home[ idx ][ 2 ], radius( spheres, idx ), ground );
+            spheres[ idx*2 ][ 2 ] = home[ idx ][ 2 ];
+            idx += 1;
+            if idx == MAX_SPHERES { break;}
+        }
+    }
+    // already settled in when the intro starts
+    regroup( -BLEND_TIME, ground );
+}
+
+// Pick the loop corners of every group around where its spheres rest, again whenever num_spheres changes. The
+// resting places belong to the spheres but the loops follow the groups. start is when the groups blend into them
+pub fn regroup( start : f32, ground : Ground ) {
+    unsafe{
+        let mut g = 0;
+        loop {
+            // loop corners spread around the middle of the group, above the terrain
+            let mut centre = [ 0.0; 4 ];
+            let mut k = 0;
+            while k < group_size( g ) {
+                centre[ 0 ] += home[ group_first( g )+k ][ 0 ] / group_size( g ) as f32;
+                centre[ 2 ] += home[ group_first( g )+k ][ 2 ] / group_size( g ) as f32;
+                k += 1;
+            }
+            let mut p = 0;
+            while p < 4 {
+                let x = centre[ 0 ] + ( rng.next_f32()-0.5 )*160.0;
+                let z = centre[ 2 ] + ( rng.next_f32()-0.5 )*160.0;
+                let y = ground( x, z );
+                groups[ g ].points[ p ] = [ x, ( if y > 0.0 { y } else { 0.0 } ) + 15.0, z, 0.0 ];
+                p += 1;
+            }
+            groups[ g ].start = start;
+            g += 1;
+            if g == GROUPS { break; }
+        }
+    }
+}
+
+// Restart the bounce of the resting spheres with the given height
+pub fn set_bounce( now : f32, scale : f32 ) {
+    unsafe{
+        bounce_start = now;
+        bounce_scale = scale;
+    }
+}
+
+// Switch a group ( every group if group >= GROUPS ) to behaviour number behaviour of BEHAVIOURS, the last one
+// if it is past the end
+pub fn set_behaviour( now : f32, group : u32, behaviour : u32 ) {
+    let behaviour = if ( behaviour as usize ) < BEHAVIOURS.len() { behaviour as usize } else { BEHAVIOURS.len()-1 };
+    unsafe{
+        let mut g = 0;
+        loop {
+            if group as usize >= GROUPS || group as usize == g {
+                groups[ g ].behaviour = BEHAVIOURS[ behaviour ];
+                groups[ g ].start = now;
+                if groups[ g ].behaviour == Behaviour::Fall {
+                    let mut k = 0;
+                    while k < group_size( g ) {
+                        let v = &mut velocity[ group_first( g )+k ];
+                        v[ 0 ] += ( rng.next_f32()-0.5 )*0.6;
+                        v[ 1 ] += 0.8 + rng.next_f32()*0.8;
+                        v[ 2 ] += ( rng.next_f32()-0.5 )*0.6;
+                        k += 1;
+                    }
+                }
+            }
+            g += 1;
+            if g == GROUPS { break; }
+        }
+    }
+}
+
+// Position u ( in control points ) around the closed loop
+fn loop_position( points : &[ [ f32; 4 ]; 4 ], u : f32 ) -> [ f32; 4 ] {
+    let segment = u as usize;
+    let t = u - segment as f32;
+    let mut pos = [ 0.0; 4 ];
+    let mut c = 0;
+    while c < 3 {
+        pos[ c ] = math_util::catmull_rom( points[ ( segment+3 )%4 ][ c ], points[ segment%4 ][ c ], points[ ( segment+1 )%4 ][ c ], points[ ( segment+2 )%4 ][ c ], t );
+        c += 1;
+    }
+    pos
+}
+
+fn sqrt( v : f32 ) -> f32 {
+    unsafe{ core::intrinsics::sqrtf32( v ) }
+}
+
+fn radius( spheres : &[ [ f32; 4 ] ], idx : usize ) -> f32 {
+    sqrt( spheres[ idx*2 ][ 3 ] )
+}
+
+// Paths and orbits are picked without looking at the terrain in between, don't let them cut through it
+fn stay_above( pos : &mut [ f32; 4 ], radius : f32, ground : Ground ) {
+    let y = physics::rest_height( pos[ 0 ], pos[ 2 ], radius, ground );
+    if pos[ 1 ] < y {
+        pos[ 1 ] = y;
+    }
+}
+
+// Where sphere k of group g wants to be for the kinematic behaviours
+fn target( now : f32, g : usize, k : usize, radius : f32, ground : Ground ) -> [ f32; 4 ] {
+    unsafe{
+        let group = &groups[ g ];
+        let rest = &home[ group_first( g )+k ];
+        match group.behaviour {
+            Behaviour::Path => {
+                loop_position( &group.points, ( now-group.start )*PATH_SPEED + ( group_size( g )-k ) as f32*PATH_SPACING )
+            }
+            Behaviour::Orbit => {
+                let centre = &group.points[ 0 ];
+                let angle = ( now-group.start )*ORBIT_SPEED*( 1.0 + ( k%3 ) as f32*0.2 ) + k as f32*6.2831853/group_size( g ) as f32;
+                let radius = 25.0 + ( k%4 ) as f32*6.0;
+                [ centre[ 0 ] + math_util::cos( angle )*radius, centre[ 1 ] + math_util::sin( angle*2.0 + k as f32 )*4.0, centre[ 2 ] + math_util::sin( angle )*radius, 0.0 ]
+            }
+            _ => {
+                let mut offset = math_util::sin( ( now-bounce_start )*0.02 )*bounce_scale;
+                if offset < 0.0 {
+                    offset = -offset;
+                }
+                [ rest[ 0 ], physics::rest_height( rest[ 0 ], rest[ 2 ], radius, ground ) + offset*rest[ 3 ], rest[ 2 ], 0.0 ]
+            }
+        }
+    }
+}
+
+fn flock( now : f32, g : usize, spheres : &mut [ [ f32; 4 ] ], ground : Ground ) {
+    unsafe{
+        let first = group_first( g );
+        let mut centre = [ 0.0; 4 ];
+        let mut heading = [ 0.0; 4 ];
+        let mut k = 0;
+        while k < group_size( g ) {
+            let mut c = 0;
+            while c < 3 {
+                centre[ c ] += spheres[ ( first+k )*2 ][ c ] / group_size( g ) as f32;
+                heading[ c ] += velocity[ first+k ][ c ] / group_size( g ) as f32;
+                c += 1;
+            }
+            k += 1;
+        }
+        let goal = loop_position( &groups[ g ].points, ( now-groups[ g ].start )*PATH_SPEED );
+
+        let mut k = 0;
+        while k < group_size( g ) {
+            let pos = spheres[ ( first+k )*2 ];
+            let v = &mut velocity[ first+k ];
+            let mut c = 0;
+            while c < 3 {
+                // cohesion, alignment and the shared goal
+                v[ c ] += ( centre[ c ]-pos[ c ] )*0.002 + ( heading[ c ]-v[ c ] )*0.05 + ( goal[ c ]-pos[ c ] )*0.001;
+                c += 1;
+            }
+            // separation
+            let mut n = 0;
+            while n < group_size( g ) {
+                if n != k {
+                    let other = spheres[ ( first+n )*2 ];
+                    let d = [ pos[ 0 ]-other[ 0 ], pos[ 1 ]-other[ 1 ], pos[ 2 ]-other[ 2 ] ];
+                    let dist2 = d[ 0 ]*d[ 0 ] + d[ 1 ]*d[ 1 ] + d[ 2 ]*d[ 2 ];
+                    if dist2 < 144.0 && dist2 > 0.0 {
+                        v[ 0 ] += d[ 0 ]/dist2*0.5;
+                        v[ 1 ] += d[ 1 ]/dist2*0.5;
+                        v[ 2 ] += d[ 2 ]/dist2*0.5;
+                    }
+                }
+                n += 1;
+            }
+            if pos[ 1 ] < ground( pos[ 0 ], pos[ 2 ] ) + 8.0 {
+                v[ 1 ] += 0.05;
+            }
+            let speed = sqrt( v[ 0 ]*v[ 0 ] + v[ 1 ]*v[ 1 ] + v[ 2 ]*v[ 2 ] );
+            if speed > FLOCK_SPEED {
+                v[ 0 ] *= FLOCK_SPEED/speed;
+                v[ 1 ] *= FLOCK_SPEED/speed;
+                v[ 2 ] *= FLOCK_SPEED/speed;
+            }
+            k += 1;
+        }
+        let mut k = 0;
+        while k < group_size( g ) {
+            let mut c = 0;
+            while c < 3 {
+                spheres[ ( first+k )*2 ][ c ] += velocity[ first+k ][ c ];
+                c += 1;
+            }
+            let r = radius( spheres, first+k );
+            stay_above( &mut spheres[ ( first+k )*2 ], r, ground );
+            k += 1;
+        }
+    }
+}
+
+fn fall( g : usize, spheres : &mut [ [ f32; 4 ] ], ground : Ground ) {
+    unsafe{
+        let mut k = 0;
+        while k < group_size( g ) {
+            let idx = group_first( g )+k;
+            let r = radius( spheres, idx );
+            physics::step( &mut spheres[ idx*2 ], &mut velocity[ idx ], r, ground );
+            k += 1;
+        }
+    }
+}
+
+// Move the spheres on by a frame
+pub fn update( now : f32, spheres : &mut [ [ f32; 4 ] ], ground : Ground ) {
+    unsafe{
+        let mut g = 0;
+        loop {
+            match groups[ g ].behaviour {
+                Behaviour::Flock => flock( now, g, spheres, ground ),
+                Behaviour::Fall => fall( g, spheres, ground ),
+                _ => {
+                    let mut blend = ( now-groups[ g ].start )/BLEND_TIME;
+                    if blend > 1.0 {
+                        blend = 1.0;
+                    }
+                    let mut k = 0;
+                    while k < group_size( g ) {
+                        let idx = group_first( g )+k;
+                        let r = radius( spheres, idx );
+                        let goal = target( now, g, k, r, ground );
+                        let mut c = 0;
+                        while c < 3 {
+                            // keep the velocity up to date for switching to the physical behaviours
+                            velocity[ idx ][ c ] = ( goal[ c ]-spheres[ idx*2 ][ c ] )*blend;
+                            spheres[ idx*2 ][ c ] += velocity[ idx ][ c ];
+                            c += 1;
+                        }
+                        stay_above( &mut spheres[ idx*2 ], r, ground );
+                        k += 1;
+                    }
+                }
+            }
+            g += 1;
+            if g == GROUPS { break; }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::terrain;
+use super::terrain::TERRAIN_SIZE;
+
+// Filtering of the terrain heights ( channel 0 ) before they are uploaded. All kernels only use the
+// cells inside the terrain and renormalise the weights at the edges so the borders do not sag
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kernel {
+    // The original smoothing. Average of the cell and its neighbours to the right and below
+    Box2x2,
+    // Equal weights over radius cells in each direction
+    Box,
+    // Binomial weights over radius cells in each direction, close to a gaussian with sigma = sqrt( radius/2 )
+    Gaussian,
+}
+
+pub struct Filter {
+    pub kernel : Kernel,
+    // at most MAX_RADIUS, unused by Box2x2
+    pub radius : usize,
+    pub passes : u32,
+    // Unsharp masking. 0 blurs, above 0 pushes the heights away from the blurred heights by this much instead
+    pub sharpen : f32,
+    // Snap the heights towards this many flat steps, 0 for no terracing
+    pub terraces : u32,
+    // 0..1, how far towards the steps
+    pub terrace_amount : f32,
+}
+
+const MAX_RADIUS : usize = 8;
+
+static mut scratch : [ f32; TERRAIN_SIZE*TERRAIN_SIZE ] = [ 0.0; TERRAIN_SIZE*TERRAIN_SIZE ];
+static mut blurred : [ f32; TERRAIN_SIZE*TERRAIN_SIZE ] = [ 0.0; TERRAIN_SIZE*TERRAIN_SIZE ];
+
+impl Filter {
+    fn weights( &self, weights : &mut [ f32; MAX_RADIUS*2+1 ] ) {
+        let radius = if self.radius > MAX_RADIUS { MAX_RADIUS } else { self.radius };
+        let mut k = 0;
+        while k <= radius*2 {
+            weights[ k ] = 1.0;
+            k += 1;
+        }
+        if self.kernel == Kernel::Gaussian {
+            // rows of pascal's triangle
+            let mut row = 1;
+            while row <= radius*2 {
+                let mut k = row-1;
+                while k > 0 {
+                    weights[ k ] += weights[ k-1 ];
+                    k -= 1;
+                }
+                row += 1;
+            }
+        }
+    }
+
+    // One direction of a separable kernel from src to dst. step is the distance between neighbouring cells
+    fn blur_1d( &self, weights : &[ f32; MAX_RADIUS*2+1 ], src : &[ f32; TERRAIN_SIZE*TERRAIN_SIZE ], dst : &mut [ f32; TERRAIN_SIZE*TERRAIN_SIZE ], horizontal : bool ) {
+        let radius = ( if self.radius > MAX_RADIUS { MAX_RADIUS } else { self.radius } ) as isize;
+        let step = if horizontal { 1 } else { TERRAIN_SIZE };
+        let mut z = 0;
+        loop{
+            let mut x = 0;
+            loop{
+                let along = ( if horizontal { x } else { z } ) as isize;
+                let mut sum = 0.0;
+                let mut total = 0.0;
+                let mut k = -radius;
+                while k <= radius {
+                    let p = along + k;
+                    if p >= 0 && p < TERRAIN_SIZE as isize {
+                        unsafe{
+                            let w = *weights.get_unchecked( ( k+radius ) as usize );
+                            sum += w * *src.get_unchecked( ( ( z*TERRAIN_SIZE + x ) as isize + k*step as isize ) as usize );
+                            total += w;
+                        }
+                    }
+                    k += 1;
+                }
+                unsafe{ *dst.get_unchecked_mut( z*TERRAIN_SIZE + x ) = sum / total; }
+                x += 1;
+                if x == TERRAIN_SIZE { break; }
+            }
+            z += 1;
+            if z == TERRAIN_SIZE { break; }
+        }
+    }
+
+    fn blur( &self, terrain : &terrain::Heightfield ) {
+        unsafe{
+            let mut xz = 0;
+            while xz < TERRAIN_SIZE*TERRAIN_SIZE {
+                scratch[ xz ] = terrain[ xz*4 ];
+                xz += 1;
+            }
+            if self.kernel == Kernel::Box2x2 {
+                let mut z = 0;
+                loop{
+                    let mut x = 0;
+                    loop{
+                        let right = if x < TERRAIN_SIZE-1 { 1 } else { 0 };
+                        let down = if z < TERRAIN_SIZE-1 { TERRAIN_SIZE } else { 0 };
+                        let xz = z*TERRAIN_SIZE + x;
+                        blurred[ xz ] = ( scratch[ xz ] + scratch[ xz+right ] + scratch[ xz+down ] + scratch[ xz+right+down ] ) / 4.0;
+                        x += 1;
+                        if x == TERRAIN_SIZE { break; }
+                    }
+                    z += 1;
+                    if z == TERRAIN_SIZE { break; }
+                }
+            } else {
+                let mut weights = [ 0f32; MAX_RADIUS*2+1 ];
+                self.weights( &mut weights );
+                self.blur_1d( &weights, &scratch, &mut blurred, true );
+                let mut xz = 0;
+                while xz < TERRAIN_SIZE*TERRAIN_SIZE {
+                    scratch[ xz ] = blurred[ xz ];
+                    xz += 1;
+                }
+                self.blur_1d( &weights, &scratch, &mut blurred, false );
+            }
+        }
+    }
+
+    pub fn apply( &self, terrain : &mut terrain::Heightfield ) {
+        let mut pass = 0;
+        while pass < self.passes {
+            self.blur( terrain );
+            unsafe{
+                let mut xz = 0;
+                while xz < TERRAIN_SIZE*TERRAIN_SIZE {
+                    let height = terrain[ xz*4 ];
+                    terrain[ xz*4 ] = if self.sharpen > 0.0 { height + ( height - blurred[ xz ] )*self.sharpen } else { blurred[ xz ] };
+                    xz += 1;
+                }
+            }
+            pass += 1;
+        }
+
+        if self.terraces > 0 {
+            let steps = self.terraces as f32;
+            let mut xz = 0;
+            while xz < TERRAIN_SIZE*TERRAIN_SIZE {
+                let height = terrain[ xz*4 ];
+                let mut step = ( height*steps ) as i32 as f32;
+                if step > height*steps {
+                    step -= 1.0;
+                }
+                terrain[ xz*4 ] = height + ( step/steps - height )*self.terrace_amount;
+                xz += 1;
+            }
+        }
+    }
+}
@@ -21,6 +21,7 @@ pub type GLsizeiptr = isize;
 pub const FALSE: GLboolean = 0;
 pub const TRIANGLES: GLenum = 0x0004;
 pub const TRIANGLE_STRIP: GLenum = 0x0005;
+pub const UNPACK_ROW_LENGTH: GLenum = 0x0CF2;
 pub const TEXTURE_2D: GLenum = 0x0DE1;
 pub const UNSIGNED_BYTE: GLenum = 0x1401;
 pub const FLOAT: GLenum = 0x1406;
@@ -28,24 +29,37 @@ pub const COLOR: GLenum = 0x1800;
 pub const RGB: GLenum = 0x1907;
 pub const RGBA: GLenum = 0x1908;
 pub const NEAREST: GLenum = 0x2600;
+pub const LINEAR: GLenum = 0x2601;
 pub const TEXTURE_MAG_FILTER: GLenum = 0x2800;
 pub const TEXTURE_MIN_FILTER: GLenum = 0x2801;
 pub const TEXTURE_WRAP_S: GLenum = 0x2802;
 pub const TEXTURE_WRAP_T: GLenum = 0x2803;
 pub const REPEAT: GLenum = 0x2901;
 pub const CLAMP_TO_EDGE: GLenum = 0x812F;
+pub const RGBA8: GLenum = 0x8058;
+pub const RGBA32F: GLenum = 0x8814;
+pub const RGBA16F: GLenum = 0x881A;
 
 pub const TEXTURE0: GLenum = 0x84C0;
+pub const TEXTURE1: GLenum = 0x84C1;
+pub const TEXTURE2: GLenum = 0x84C2;
+pub const TEXTURE3: GLenum = 0x84C3;
+pub const TEXTURE4: GLenum = 0x84C4;
+pub const TEXTURE5: GLenum = 0x84C5;
+pub const TEXTURE6: GLenum = 0x84C6;
 pub const FRAGMENT_SHADER: GLenum = 0x8B30;
 pub const VERTEX_SHADER: GLenum = 0x8B31;
 pub const COMPILE_STATUS: GLenum = 0x8B81;
 pub const LINK_STATUS: GLenum = 0x8B82;
 pub const ARRAY_BUFFER: GLenum = 0x8892;
 pub const STATIC_DRAW: GLenum = 0x88E4;
+pub const COLOR_ATTACHMENT0: GLenum = 0x8CE0;
+pub const FRAMEBUFFER: GLenum = 0x8D40;
 
 const ActiveTextureIdx: u16 = 1;
 const AttachShaderIdx: u16 = 2;     // 
 const BindBufferIdx: u16 = 8;
+const BindFramebufferIdx: u16 = 17;
 const BindTextureIdx: u16 = 22;
 const BindVertexArrayIdx: u16 = 26;
 const BufferDataIdx: u16 = 40;
@@ -56,6 +70,7 @@ const RasterPos2fIdx : u16 = 51;
 const CreateProgramIdx: u16 = 96;
 const ClearBufferfvIdx: u16 = 49;
 const CompileShaderIdx: u16 = 73;
+const CopyTexSubImage2DIdx: u16 = 93;
 const CreateShaderIdx: u16 = 101;
 const DetachShaderIdx: u16 = 128;
 const DrawArraysIdx: u16 = 135;
@@ -63,7 +78,9 @@ const DrawArraysIdx: u16 = 135;
 
 const RectiIdx: u16 = 136;
 const EnableVertexAttribArrayIdx: u16 = 157;
+const FramebufferTexture2DIdx: u16 = 168;
 const GenBuffersIdx: u16 = 175;
+const GenFramebuffersIdx: u16 = 180;
 const GenTexturesIdx: u16 = 183;
 const GenVertexArraysIdx: u16 = 185;
 const GetProgramInfoLogIdx: u16 = 254;
@@ -74,10 +91,13 @@ const GetUniformLocationIdx: u16 = 313;
 const LinkProgramIdx: u16 = 350;
 const ListBaseIdx: u16 = 370;
 
+const PixelStoreiIdx: u16 = 430;
 const ShaderSourceIdx: u16 = 479;
 const TexImage2DIdx: u16 = 498;
 const TexParameteriIdx: u16 = 504;
+const TexSubImage2DIdx: u16 = 510;
 const Uniform1fIdx: u16 = 539;
+const Uniform1iIdx: u16 = 541;
 const Uniform3fvIdx: u16 = 556;
 const Uniform4fvIdx: u16 = 564;
 const UseProgramIdx: u16 = 591;
@@ -118,6 +138,7 @@ static LOAD_DESC: &'static [(u16, &'static str)] = &[
 
     (GetUniformLocationIdx, "glGetUniformLocation\0"),
     (Uniform4fvIdx, "glUniform4fv\0"),
+    (Uniform1iIdx, "glUniform1i\0"),
   
     // Texture
     (GenTexturesIdx, "glGenTextures\0"),
@@ -125,8 +146,17 @@ static LOAD_DESC: &'static [(u16, &'static str)] = &[
     (ActiveTextureIdx, "glActiveTexture\0"),
     (TexImage2DIdx, "glTexImage2D\0"),
     (TexParameteriIdx, "glTexParameteri\0"),
+    (TexSubImage2DIdx, "glTexSubImage2D\0"),
+    #[cfg(feature = "tiled_terrain")]
+    (PixelStoreiIdx, "glPixelStorei\0"),
 
 
+    // Post processing
+    (GenFramebuffersIdx, "glGenFramebuffers\0"),
+    (BindFramebufferIdx, "glBindFramebuffer\0"),
+    (FramebufferTexture2DIdx, "glFramebufferTexture2D\0"),
+    (CopyTexSubImage2DIdx, "glCopyTexSubImage2D\0"),
+
     (ListBaseIdx, "glListBase\0"),
     (CallListsIdx, "glCallLists\0"),
     (RasterPos2fIdx, "glRasterPos2f\0"),
@@ -240,6 +270,10 @@ pub unsafe fn Uniform1f(location: GLint, v0: GLfloat) -> () {
     mem::transmute::<_, extern "system" fn(GLint, GLfloat) -> ()>(*GL_API.get_unchecked(Uniform1fIdx as usize))(location, v0)
 }
 
+pub unsafe fn Uniform1i(location: GLint, v0: GLint) -> () {
+    mem::transmute::<_, extern "system" fn(GLint, GLint) -> ()>(*GL_API.get_unchecked(Uniform1iIdx as usize))(location, v0)
+}
+
 pub unsafe fn Uniform4fv(location: GLint, count: GLsizei, value: *const GLfloat) -> () {
     mem::transmute::<_, extern "system" fn(GLint, GLsizei, *const GLfloat) -> ()>(*GL_API.get_unchecked(Uniform4fvIdx as usize))(location, count, value)
 }
@@ -256,10 +290,35 @@ pub unsafe fn TexImage2D(target: GLenum, level: GLint, internalformat: GLenum, w
     mem::transmute::<_, extern "system" fn(GLenum, GLint, GLenum, GLsizei, GLsizei, GLint, GLenum, GLenum, *const CVoid) -> ()>(*GL_API.get_unchecked(TexImage2DIdx as usize))(target, level, internalformat, width, height, border, format, type_, pixels)
 }
 
+pub unsafe fn CopyTexSubImage2D(target: GLenum, level: GLint, xoffset: GLint, yoffset: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei) -> () {
+    mem::transmute::<_, extern "system" fn(GLenum, GLint, GLint, GLint, GLint, GLint, GLsizei, GLsizei) -> ()>(*GL_API.get_unchecked(CopyTexSubImage2DIdx as usize))(target, level, xoffset, yoffset, x, y, width, height)
+}
+
+pub unsafe fn GenFramebuffers(n: GLsizei, framebuffers: *mut GLuint) -> () {
+    mem::transmute::<_, extern "system" fn(GLsizei, *mut GLuint) -> ()>(*GL_API.get_unchecked(GenFramebuffersIdx as usize))(n, framebuffers)
+}
+
+pub unsafe fn BindFramebuffer(target: GLenum, framebuffer: GLuint) -> () {
+    mem::transmute::<_, extern "system" fn(GLenum, GLuint) -> ()>(*GL_API.get_unchecked(BindFramebufferIdx as usize))(target, framebuffer)
+}
+
+pub unsafe fn FramebufferTexture2D(target: GLenum, attachment: GLenum, textarget: GLenum, texture: GLuint, level: GLint) -> () {
+    mem::transmute::<_, extern "system" fn(GLenum, GLenum, GLenum, GLuint, GLint) -> ()>(*GL_API.get_unchecked(FramebufferTexture2DIdx as usize))(target, attachment, textarget, texture, level)
+}
+
 pub unsafe fn TexParameteri(target: GLenum, pname: GLenum, param: GLint) -> () {
     mem::transmute::<_, extern "system" fn(GLenum, GLenum, GLint) -> ()>(*GL_API.get_unchecked(TexParameteriIdx as usize))(target, pname, param)
 }
 
+pub unsafe fn TexSubImage2D(target: GLenum, level: GLint, xoffset: GLint, yoffset: GLint, width: GLsizei, height: GLsizei, format: GLenum, type_: GLenum, pixels: *const CVoid) -> () {
+    mem::transmute::<_, extern "system" fn(GLenum, GLint, GLint, GLint, GLsizei, GLsizei, GLenum, GLenum, *const CVoid) -> ()>(*GL_API.get_unchecked(TexSubImage2DIdx as usize))(target, level, xoffset, yoffset, width, height, format, type_, pixels)
+}
+
+#[cfg(feature = "tiled_terrain")]
+pub unsafe fn PixelStorei(pname: GLenum, param: GLint) -> () {
+    mem::transmute::<_, extern "system" fn(GLenum, GLint) -> ()>(*GL_API.get_unchecked(PixelStoreiIdx as usize))(pname, param)
+}
+
 pub unsafe fn ActiveTexture(texture: GLenum) -> () {
     mem::transmute::<_, extern "system" fn(GLenum) -> ()>(*GL_API.get_unchecked(ActiveTextureIdx as usize))(texture)
 }
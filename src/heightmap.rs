@@ -0,0 +1,440 @@
+// Export of the generated terrain and import of externally authored heightmaps. Development only.
+//
+// terrain_out.png  16 bit grayscale png of the uploaded heights, 0..1 mapped to 0..65535
+// terrain_out.raw  the same heights as little endian f32s
+// heightmap.png    if present, loaded instead of generating the terrain ( TERRAIN_SIZE squared 8 or 16 bit grayscale,
+//                  not interlaced, as written by most paint programs )
+// heightmap.raw    if not, this ( TERRAIN_SIZE squared little endian f32s )
+// heightmap.r16    or this ( TERRAIN_SIZE squared little endian u16s )
+use super::terrain;
+use super::terrain::TERRAIN_SIZE;
+use super::util;
+
+// Big enough for the largest of the files. The png is stored uncompressed
+static mut file_buffer : [ u8; TERRAIN_SIZE*TERRAIN_SIZE*4 + 4096 ] = [ 0; TERRAIN_SIZE*TERRAIN_SIZE*4 + 4096 ];
+static mut file_pos : usize = 0;
+static mut crc_table : [ u32; 256 ] = [ 0; 256 ];
+// Inflated png rows, each with its filter byte
+static mut image_buffer : [ u8; TERRAIN_SIZE*( 1+TERRAIN_SIZE*2 ) ] = [ 0; TERRAIN_SIZE*( 1+TERRAIN_SIZE*2 ) ];
+
+unsafe fn put_u8( val : u8 ) {
+    *file_buffer.get_unchecked_mut( file_pos ) = val;
+    file_pos += 1;
+}
+
+unsafe fn put_u16_be( val : u16 ) {
+    put_u8( ( val >> 8 ) as u8 );
+    put_u8( val as u8 );
+}
+
+unsafe fn put_u32_be( val : u32 ) {
+    put_u16_be( ( val >> 16 ) as u16 );
+    put_u16_be( val as u16 );
+}
+
+unsafe fn crc32( data : &[u8] ) -> u32 {
+    if crc_table[ 1 ] == 0 {
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 { 0xedb88320 ^ ( c >> 1 ) } else { c >> 1 };
+                k += 1;
+            }
+            crc_table[ n ] = c;
+            n += 1;
+        }
+    }
+    let mut crc = 0xffffffffu32;
+    for b in data {
+        crc = crc_table[ ( ( crc ^ *b as u32 ) & 0xff ) as usize ] ^ ( crc >> 8 );
+    }
+    crc ^ 0xffffffff
+}
+
+// Chunk data must already be written after the 8 bytes reserved for the length and type at chunk_start
+unsafe fn end_chunk( chunk_start : usize ) {
+    let length = file_pos - chunk_start - 8;
+    let end = file_pos;
+    file_pos = chunk_start;
+    put_u32_be( length as u32 );
+    file_pos = end;
+    let crc = crc32( &file_buffer[ chunk_start+4 .. end ] );
+    put_u32_be( crc );
+}
+
+unsafe fn begin_chunk( name : &[u8;4] ) -> usize {
+    let chunk_start = file_pos;
+    file_pos += 4;
+    for b in name {
+        put_u8( *b );
+    }
+    chunk_start
+}
+
+fn to_u16( height : f32 ) -> u16 {
+    if height <= 0.0 { 0 } else if height >= 1.0 { 65535 } else { ( height*65535.0 ) as u16 }
+}
+
+pub fn export( src : &terrain::Heightfield ) {
+    unsafe{
+        super::log!( "Export terrain" );
+        file_pos = 0;
+        for b in &[ 0x89u8, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a ] {
+            put_u8( *b );
+        }
+
+        let chunk = begin_chunk( b"IHDR" );
+        put_u32_be( TERRAIN_SIZE as u32 );
+        put_u32_be( TERRAIN_SIZE as u32 );
+        put_u8( 16 );       // bit depth
+        put_u8( 0 );        // grayscale
+        put_u8( 0 );        // deflate
+        put_u8( 0 );        // adaptive filtering
+        put_u8( 0 );        // no interlace
+        end_chunk( chunk );
+
+        // zlib stream made of stored deflate blocks, one per two rows
+        let chunk = begin_chunk( b"IDAT" );
+        put_u8( 0x78 );
+        put_u8( 0x01 );
+        let mut adler_a : u32 = 1;
+        let mut adler_b : u32 = 0;
+        let mut z = 0;
+        loop {
+            let block_len : u16 = ( 2*( 1 + TERRAIN_SIZE*2 ) ) as u16;
+            put_u8( if z == TERRAIN_SIZE-2 { 1 } else { 0 } );
+            put_u8( block_len as u8 );
+            put_u8( ( block_len >> 8 ) as u8 );
+            put_u8( !block_len as u8 );
+            put_u8( ( !block_len >> 8 ) as u8 );
+            let block_start = file_pos;
+            let mut row = 0;
+            loop {
+                put_u8( 0 );        // no filter
+                let mut x = 0;
+                loop {
+                    put_u16_be( to_u16( *src.get_unchecked( ( ( z+row )*TERRAIN_SIZE + x )*4 ) ) );
+                    x += 1;
+                    if x == TERRAIN_SIZE { break; }
+                }
+                row += 1;
+                if row == 2 { break; }
+            }
+            for b in &file_buffer[ block_start .. file_pos ] {
+                adler_a = ( adler_a + *b as u32 ) % 65521;
+                adler_b = ( adler_b + adler_a ) % 65521;
+            }
+            z += 2;
+            if z == TERRAIN_SIZE { break; }
+        }
+        put_u32_be( ( adler_b << 16 ) | adler_a );
+        end_chunk( chunk );
+
+        let chunk = begin_chunk( b"IEND" );
+        end_chunk( chunk );
+        util::write_file( "terrain_out.png\0", &file_buffer[ ..file_pos ] );
+
+        file_pos = 0;
+        let mut xz = 0;
+        loop {
+            let bits = src.get_unchecked( xz*4 ).to_bits();
+            put_u8( bits as u8 );
+            put_u8( ( bits >> 8 ) as u8 );
+            put_u8( ( bits >> 16 ) as u8 );
+            put_u8( ( bits >> 24 ) as u8 );
+            xz += 1;
+            if xz == TERRAIN_SIZE*TERRAIN_SIZE { break; }
+        }
+        util::write_file( "terrain_out.raw\0", &file_buffer[ ..file_pos ] );
+    }
+}
+
+// Reads the zlib stream of a png, least significant bit first
+struct BitReader<'a> {
+    data : &'a [ u8 ],
+    pos : usize,
+    bits : u32,
+    count : u32,
+}
+
+impl<'a> BitReader<'a> {
+    // None when the data runs out
+    fn get( &mut self, n : u32 ) -> Option< u32 > {
+        while self.count < n {
+            if self.pos >= self.data.len() { return None; }
+            self.bits |= ( self.data[ self.pos ] as u32 ) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let val = self.bits & ( ( 1u32 << n ) - 1 );
+        self.bits >>= n;
+        self.count -= n;
+        Some( val )
+    }
+}
+
+// Canonical huffman code as the number of codes of each length and the symbols in code order
+struct Huffman {
+    counts : [ u16; 16 ],
+    symbols : [ u16; 288 ],
+}
+
+impl Huffman {
+    fn new( lengths : &[ u8 ] ) -> Huffman {
+        let mut h = Huffman{ counts: [ 0; 16 ], symbols: [ 0; 288 ] };
+        for l in lengths {
+            h.counts[ *l as usize ] += 1;
+        }
+        h.counts[ 0 ] = 0;
+        let mut offsets = [ 0u16; 16 ];
+        let mut l = 1;
+        while l < 15 {
+            offsets[ l+1 ] = offsets[ l ] + h.counts[ l ];
+            l += 1;
+        }
+        let mut s = 0;
+        while s < lengths.len() {
+            let l = lengths[ s ] as usize;
+            if l != 0 {
+                h.symbols[ offsets[ l ] as usize ] = s as u16;
+                offsets[ l ] += 1;
+            }
+            s += 1;
+        }
+        h
+    }
+
+    fn decode( &self, input : &mut BitReader ) -> Option< usize > {
+        let mut code : i32 = 0;
+        let mut first : i32 = 0;
+        let mut index : i32 = 0;
+        let mut l = 1;
+        while l < 16 {
+            code |= input.get( 1 )? as i32;
+            let count = self.counts[ l ] as i32;
+            if code - first < count {
+                return Some( self.symbols[ ( index + code - first ) as usize ] as usize );
+            }
+            index += count;
+            first = ( first + count ) << 1;
+            code <<= 1;
+            l += 1;
+        }
+        None
+    }
+}
+
+static LENGTH_BASE : [ u16; 29 ] = [ 3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258 ];
+static LENGTH_EXTRA : [ u8; 29 ] = [ 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0 ];
+static DIST_BASE : [ u16; 30 ] = [ 1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577 ];
+static DIST_EXTRA : [ u8; 30 ] = [ 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13 ];
+// Order the code length code lengths are stored in
+static CODE_LENGTH_ORDER : [ u8; 19 ] = [ 16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15 ];
+
+// Symbols of one huffman coded block into out from out_pos. Returns the new out_pos
+fn inflate_codes( input : &mut BitReader, out : &mut [ u8 ], mut out_pos : usize, lengths : &Huffman, distances : &Huffman ) -> Option< usize > {
+    loop {
+        let sym = lengths.decode( input )?;
+        if sym < 256 {
+            if out_pos >= out.len() { return None; }
+            out[ out_pos ] = sym as u8;
+            out_pos += 1;
+        } else if sym == 256 {
+            return Some( out_pos );
+        } else {
+            let sym = sym - 257;
+            if sym >= 29 { return None; }
+            let length = LENGTH_BASE[ sym ] as usize + input.get( LENGTH_EXTRA[ sym ] as u32 )? as usize;
+            let dsym = distances.decode( input )?;
+            if dsym >= 30 { return None; }
+            let dist = DIST_BASE[ dsym ] as usize + input.get( DIST_EXTRA[ dsym ] as u32 )? as usize;
+            if dist > out_pos || out_pos + length > out.len() { return None; }
+            let mut i = 0;
+            while i < length {
+                out[ out_pos ] = out[ out_pos-dist ];
+                out_pos += 1;
+                i += 1;
+            }
+        }
+    }
+}
+
+// Inflate the zlib stream in data into out. Returns the number of bytes written
+fn inflate( data : &[ u8 ], out : &mut [ u8 ] ) -> Option< usize > {
+    // skip the zlib header, the adler32 at the end is not checked
+    let mut input = BitReader{ data: data.get( 2.. )?, pos: 0, bits: 0, count: 0 };
+    let mut out_pos = 0;
+    loop {
+        let last = input.get( 1 )?;
+        let kind = input.get( 2 )?;
+        if kind == 0 {
+            // stored, from the next byte boundary
+            input.bits = 0;
+            input.count = 0;
+            let length = input.get( 16 )? as usize;
+            input.get( 16 )?;
+            if input.pos + length > input.data.len() || out_pos + length > out.len() { return None; }
+            out[ out_pos .. out_pos+length ].copy_from_slice( &input.data[ input.pos .. input.pos+length ] );
+            input.pos += length;
+            out_pos += length;
+        } else if kind == 1 {
+            let mut lengths = [ 0u8; 288 ];
+            let mut s = 0;
+            while s < 288 {
+                lengths[ s ] = if s < 144 { 8 } else if s < 256 { 9 } else if s < 280 { 7 } else { 8 };
+                s += 1;
+            }
+            out_pos = inflate_codes( &mut input, out, out_pos, &Huffman::new( &lengths ), &Huffman::new( &[ 5; 30 ] ) )?;
+        } else if kind == 2 {
+            let num_lengths = input.get( 5 )? as usize + 257;
+            let num_distances = input.get( 5 )? as usize + 1;
+            let num_codes = input.get( 4 )? as usize + 4;
+            if num_lengths > 286 || num_distances > 30 { return None; }
+            let mut code_lengths = [ 0u8; 19 ];
+            let mut i = 0;
+            while i < num_codes {
+                code_lengths[ CODE_LENGTH_ORDER[ i ] as usize ] = input.get( 3 )? as u8;
+                i += 1;
+            }
+            let code_lengths = Huffman::new( &code_lengths );
+            let mut lengths = [ 0u8; 286+30 ];
+            let mut i = 0;
+            while i < num_lengths + num_distances {
+                let sym = code_lengths.decode( &mut input )?;
+                if sym < 16 {
+                    lengths[ i ] = sym as u8;
+                    i += 1;
+                } else {
+                    let ( value, repeat ) = match sym {
+                        16 => { if i == 0 { return None; } ( lengths[ i-1 ], 3 + input.get( 2 )? as usize ) },
+                        17 => ( 0, 3 + input.get( 3 )? as usize ),
+                        _ => ( 0, 11 + input.get( 7 )? as usize ),
+                    };
+                    if i + repeat > num_lengths + num_distances { return None; }
+                    let mut r = 0;
+                    while r < repeat {
+                        lengths[ i ] = value;
+                        i += 1;
+                        r += 1;
+                    }
+                }
+            }
+            out_pos = inflate_codes( &mut input, out, out_pos, &Huffman::new( &lengths[ ..num_lengths ] ),
+                &Huffman::new( &lengths[ num_lengths .. num_lengths+num_distances ] ) )?;
+        } else {
+            return None;
+        }
+        if last == 1 {
+            return Some( out_pos );
+        }
+    }
+}
+
+fn get_u32_be( b : &[ u8 ] ) -> u32 {
+    ( b[ 0 ] as u32 ) << 24 | ( b[ 1 ] as u32 ) << 16 | ( b[ 2 ] as u32 ) << 8 | b[ 3 ] as u32
+}
+
+// Heights of a grayscale png of length bytes in file_buffer. False if it is not one that can be read
+unsafe fn import_png( length : usize, dst : &mut terrain::Heightfield ) -> bool {
+    let file = &mut file_buffer[ ..length ];
+    if length < 33 || file[ 1..4 ] != *b"PNG" || get_u32_be( &file[ 16.. ] ) as usize != TERRAIN_SIZE || get_u32_be( &file[ 20.. ] ) as usize != TERRAIN_SIZE
+        || ( file[ 24 ] != 8 && file[ 24 ] != 16 ) || file[ 25 ] != 0 || file[ 28 ] != 0 {
+        super::log!( "heightmap.png must be TERRAIN_SIZE square 8 or 16 bit grayscale and not interlaced" );
+        return false;
+    }
+    let bytes = file[ 24 ] as usize/8;
+
+    // move the data of the IDAT chunks together at the start of the file
+    let mut zlib_length = 0;
+    let mut pos = 8;
+    while pos + 12 <= length {
+        let chunk_length = get_u32_be( &file[ pos.. ] ) as usize;
+        if chunk_length > length - pos - 12 { break; }
+        if file[ pos+4 .. pos+8 ] == *b"IDAT" {
+            file.copy_within( pos+8 .. pos+8+chunk_length, zlib_length );
+            zlib_length += chunk_length;
+        }
+        pos += 12 + chunk_length;
+    }
+
+    let stride = 1 + TERRAIN_SIZE*bytes;
+    let rows = &mut image_buffer[ ..TERRAIN_SIZE*stride ];
+    if inflate( &file_buffer[ ..zlib_length ], rows ) != Some( TERRAIN_SIZE*stride ) {
+        super::log!( "heightmap.png could not be inflated" );
+        return false;
+    }
+
+    // undo the filters in place, then convert
+    let mut z = 0;
+    while z < TERRAIN_SIZE {
+        let row = z*stride + 1;
+        let filter = rows[ row-1 ];
+        let mut i = 0;
+        while i < TERRAIN_SIZE*bytes {
+            let left = if i >= bytes { rows[ row+i-bytes ] as i32 } else { 0 };
+            let up = if z > 0 { rows[ row+i-stride ] as i32 } else { 0 };
+            let up_left = if z > 0 && i >= bytes { rows[ row+i-stride-bytes ] as i32 } else { 0 };
+            let predicted = match filter {
+                1 => left,
+                2 => up,
+                3 => ( left+up ) / 2,
+                4 => {
+                    let p = left + up - up_left;
+                    let ( pa, pb, pc ) = ( ( p-left ).abs(), ( p-up ).abs(), ( p-up_left ).abs() );
+                    if pa <= pb && pa <= pc { left } else if pb <= pc { up } else { up_left }
+                },
+                _ => 0,
+            };
+            rows[ row+i ] = ( rows[ row+i ] as i32 + predicted ) as u8;
+            i += 1;
+        }
+        let mut x = 0;
+        while x < TERRAIN_SIZE {
+            *dst.get_unchecked_mut( ( z*TERRAIN_SIZE + x )*4 ) = if bytes == 2 {
+                ( ( rows[ row+x*2 ] as u32 ) << 8 | rows[ row+x*2+1 ] as u32 ) as f32 / 65535.0
+            } else {
+                rows[ row+x ] as f32 / 255.0
+            };
+            x += 1;
+        }
+        z += 1;
+    }
+    true
+}
+
+// Replace the heights with heightmap.png, heightmap.raw or heightmap.r16. Returns false if none can be read
+pub fn import( dst : &mut terrain::Heightfield ) -> bool {
+    unsafe{
+        let mut xz = 0;
+        let length = util::read_file( "heightmap.png\0", &mut file_buffer );
+        if length > 0 {
+            super::log!( "Import heightmap.png" );
+            if import_png( length, dst ) {
+                return true;
+            }
+        }
+        if util::read_file( "heightmap.raw\0", &mut file_buffer ) >= TERRAIN_SIZE*TERRAIN_SIZE*4 {
+            super::log!( "Import heightmap.raw" );
+            loop {
+                let b = &file_buffer[ xz*4 .. xz*4+4 ];
+                *dst.get_unchecked_mut( xz*4 ) = f32::from_bits( b[ 0 ] as u32 | ( b[ 1 ] as u32 ) << 8 | ( b[ 2 ] as u32 ) << 16 | ( b[ 3 ] as u32 ) << 24 );
+                xz += 1;
+                if xz == TERRAIN_SIZE*TERRAIN_SIZE { break; }
+            }
+            return true;
+        }
+        if util::read_file( "heightmap.r16\0", &mut file_buffer ) >= TERRAIN_SIZE*TERRAIN_SIZE*2 {
+            super::log!( "Import heightmap.r16" );
+            loop {
+                let b = &file_buffer[ xz*2 .. xz*2+2 ];
+                *dst.get_unchecked_mut( xz*4 ) = ( b[ 0 ] as u32 | ( b[ 1 ] as u32 ) << 8 ) as f32 / 65535.0;
+                xz += 1;
+                if xz == TERRAIN_SIZE*TERRAIN_SIZE { break; }
+            }
+            return true;
+        }
+    }
+    false
+}
@@ -2,10 +2,26 @@ use super::math_util;
 use super::gl;
 use super::gl_util;
 use super::random;
+use super::music;
+use super::terrain;
+use super::terrain::TerrainGenerator;
+use super::terrain::TERRAIN_SIZE;
+use super::filter;
+use super::choreo;
+use super::camera_track;
+use super::post;
+use super::scene;
+use super::sphere_materials;
+#[cfg(feature = "tiled_terrain")]
+use super::terrain::TileGenerator;
+#[cfg(feature = "seed_browser")]
+use super::seed_browser;
+#[cfg(feature = "tiled_terrain")]
+use super::tiles;
 use core::arch::x86;
 
 use gl::CVoid;
-use core::mem::{size_of,transmute};
+use core::mem::size_of;
 use core::ops::{Add,Sub,Mul};
 
 // Floating point constants picked for compressibility
@@ -16,37 +32,56 @@ pub const FP_0_20 : f32 = 0.2001953125f32;
 pub const FP_1_32  : f32 = 1.3203125000f32;     // 1.32f    0x3fa90000
 pub const FP_1_54 : f32 = 1.5390625000f32;
 
-// The dynamic part of the world is 80 spheres + camera and light
-pub const CAMERA_POS_IDX : usize = 80*2;
-pub const CAMERA_ROT_IDX : usize = 80*2+1;
-pub const CAMERA_CUT_INFO : usize = 80*2+2;
-pub const num_spheres : usize = 80;
-pub const sphere_extras : usize = 2;
+// The dynamic part of the world is up to MAX_SPHERES spheres in the sphere texture + the scene uniforms: camera, light,
+// audio features and the part of the terrain in the texture. num_spheres can change while it runs
+pub const MAX_SPHERES : usize = scene::MAX_SPHERES;
+pub static mut num_spheres : usize = 80;
 
 static mut shader_prog : gl::GLuint = 0;
 static mut vertex_array_id : gl::GLuint = 0;
 
 static mut rng : random::Rng = random::Rng{seed: core::num::Wrapping(21431249)};
 
-static mut global_spheres: [ [ f32; 4]; (num_spheres+sphere_extras)*2] = [ [ 0f32; 4]; (num_spheres+sphere_extras)*2 ];  
-
-fn smooth( pixels: &mut [ f32; 512*513*4 ]) {
-    unsafe{
-        let mut xy = 0;
-        loop{
-            let offset = xy*4;
-            let mut val  = *pixels.get_unchecked( offset );
-            val += pixels.get_unchecked( offset+4 );
-            val += pixels.get_unchecked( offset+2048 );
-            val += pixels.get_unchecked( offset+2052 );
-            *pixels.get_unchecked_mut( offset ) = val / 4.0;
-            xy += 1;
-            if xy == 511*511 { break; }
-        }
-    }
-}
-
-static mut src_terrain  : [ f32; 512*513*4 ] = [ 0.0; 512*513*4 ];
+static mut global_spheres: [ [ f32; 4]; MAX_SPHERES*2] = [ [ 0f32; 4]; MAX_SPHERES*2 ];  
+static mut global_scene: scene::Scene = scene::EMPTY;
+static mut scene_locations : scene::SceneLocations = scene::SceneLocations::UNLOCATED;
+static mut num_spheres_location : gl::GLint = 0;
+
+static mut src_terrain  : terrain::Heightfield = [ 0.0; TERRAIN_SIZE*TERRAIN_SIZE*4 ];
+
+// The generator is picked with the terrain_fbm, terrain_ridged and terrain_erosion features, the original lumps
+// when none is set. Change the seeds and parameters here
+// Keep the density of the samples the same at every resolution ( 700,000 at 512 )
+#[cfg(not(any(feature = "terrain_fbm", feature = "terrain_ridged", feature = "terrain_erosion")))]
+static TERRAIN_GENERATOR : terrain::LumpCharge = terrain::LumpCharge{ seed: 7923129, num_lumps: 50, samples: (TERRAIN_SIZE/256)*(TERRAIN_SIZE/256)*175_000 };
+#[cfg(feature = "terrain_fbm")]
+static TERRAIN_GENERATOR : terrain::Fbm = terrain::Fbm{ seed: 1234, basis: terrain::NoiseBasis::Perlin, octaves: 6, frequency: 4.0, gain: 0.5, base: 0.1, height: 0.8 };
+#[cfg(feature = "terrain_ridged")]
+static TERRAIN_GENERATOR : terrain::RidgedMultifractal = terrain::RidgedMultifractal{ seed: 1234, octaves: 6, frequency: 3.0, gain: 2.0, offset: 1.0, base: 0.1, height: 0.8 };
+#[cfg(feature = "terrain_erosion")]
+static TERRAIN_GENERATOR : terrain::HydraulicErosion = terrain::HydraulicErosion{ base: &terrain::Fbm{ seed: 1234, basis: terrain::NoiseBasis::Perlin, octaves: 6, frequency: 4.0, gain: 0.5, base: 0.1, height: 0.8 },
+     seed: 4321, droplets: 70_000, lifetime: 64, inertia: 0.05, capacity: 4.0, erode_rate: 0.3, deposit_rate: 0.3, evaporate_rate: 0.01 };
+#[cfg(any(all(feature = "terrain_fbm", feature = "terrain_ridged"), all(feature = "terrain_fbm", feature = "terrain_erosion"), all(feature = "terrain_ridged", feature = "terrain_erosion")))]
+compile_error!( "terrain_fbm, terrain_ridged and terrain_erosion are mutually exclusive" );
+// One pass of the original 2x2 smoothing. e.g. filter::Filter{ kernel: filter::Kernel::Gaussian, radius: 2, passes: 2, sharpen: 0.0, terraces: 12, terrace_amount: 0.5 }
+static TERRAIN_FILTER : filter::Filter = filter::Filter{ kernel: filter::Kernel::Box2x2, radius: 1, passes: 1, sharpen: 0.0, terraces: 0, terrace_amount: 0.0 };
+// Effects of the post pass, None turns one off. They are all off and Clip tonemaps like before HDR so the intro
+// looks as it did. The sequence opts in with MODE_EXPOSURE and post::set_effects, e.g. tonemap: post::Tonemap::Aces
+// bloom: Some( post::Bloom{ threshold: 1.0, intensity: 0.4, radius: 12.0 } )
+// vignette: Some( post::Vignette{ strength: 0.35, inner: 0.5, outer: 1.2 } )
+// grain: Some( post::Grain{ amount: 0.02 } )
+// grade: Some( post::Grade{ lift: [ 0.02, 0.01, 0.0 ], gain: [ 1.0, 0.97, 0.9 ], contrast: 0.3, saturation: 0.9, shadows: [ 0.95, 1.0, 1.05 ], highlights: [ 1.05, 1.0, 0.92 ], amount: 1.0 } )
+static POST_EFFECTS : post::Effects = post::Effects{
+    tonemap: post::Tonemap::Clip,
+    exposure: 0.0,
+    bloom: None,
+    vignette: None,
+    grain: None,
+    grade: None,
+};
+// Generator of the unbounded terrain streamed around the camera
+#[cfg(feature = "tiled_terrain")]
+static TILE_GENERATOR : terrain::Fbm = terrain::Fbm{ seed: 1234, basis: terrain::NoiseBasis::Perlin, octaves: 6, frequency: 4.0, gain: 0.5, base: 0.1, height: 0.8 };
 static mut tex_buffer_id : gl::GLuint = 0;
 
 #[cfg(feature = "logger")]
@@ -58,24 +93,41 @@ static mut moving_camera : bool  = false;
 static mut rotating_camera : bool  = false;
 
 static mut camera_velocity : [ f32; 4] = [ 0.0; 4];
+// Change of cam_rot per frame, pitch, yaw, roll and field of view
 static mut camera_rot_speed : [ f32; 4] = [ 0.0; 4];
 
 static mut camera_mode : u32 = 0;
-static mut sphere_scale : f32 = 0.0;
+// Poses captured with capture_pose this session, numbered on from POSES
+#[cfg(feature = "logger")]
+static mut captured_poses : usize = 0;
+// Keyframed track the camera is following and when it started, the seeded pans move it when None
+static mut active_track : Option<&'static camera_track::Track> = None;
+static mut track_start : f32 = 0.0;
+// Height the pans keep above the terrain and water, 0 lets them go through it
+static mut camera_clearance : f32 = 0.0;
+
+// Frames of its path the camera looks ahead when keeping clear of the terrain, in steps of LOOKAHEAD_STEP
+const LOOKAHEAD : usize = 90;
+const LOOKAHEAD_STEP : usize = 10;
+// Fraction of the way up to the clearance height climbed each frame
+const LIFT_RATE : f32 = 0.05;
+// A higher climb than this ahead turns the camera towards the lower side, by TURN_RATE radians a frame
+const MAX_CLIMB : f32 = 15.0;
+const TURN_RATE : f32 = 0.01;
 
 #[cfg(feature = "logger")]
 pub fn set_pos( x: i32, y: i32, ctrl : bool ) {
     unsafe{
         if moving_camera {
             if ctrl{
-                global_spheres[ CAMERA_POS_IDX ][ 1 ] += ( y-old_y) as f32 / 32.0;
+                global_scene.cam_pos[ 1 ] += ( y-old_y) as f32 / 32.0;
             } else {
-                global_spheres[ CAMERA_POS_IDX ][ 0 ] += ( x-old_x) as f32 / 32.0;
-                global_spheres[ CAMERA_POS_IDX ][ 2 ] += ( y-old_y) as f32 / 32.0;
+                global_scene.cam_pos[ 0 ] += ( x-old_x) as f32 / 32.0;
+                global_scene.cam_pos[ 2 ] += ( y-old_y) as f32 / 32.0;
             }
         } else if rotating_camera {
-            global_spheres[ CAMERA_ROT_IDX ][ 0 ] += ( y-old_y) as f32 / 1024.0;
-            global_spheres[ CAMERA_ROT_IDX ][ 1 ] += ( x-old_x) as f32 / 1024.0;
+            global_scene.cam_rot[ 0 ] += ( y-old_y) as f32 / 1024.0;
+            global_scene.cam_rot[ 1 ] += ( x-old_x) as f32 / 1024.0;
         }
         old_x = x;
         old_y = y;
@@ -110,12 +162,39 @@ pub fn lbutton_down( x: i32, y: i32 ) {
     }
 }
 
+// Append the camera as it is now to timeline.txt as a POSES entry and a MODE_CAM_POSE event to paste
+// into the SEQUENCE
+#[cfg(feature = "logger")]
+pub fn capture_pose( ) {
+    let mut text : [ u8; 512 ] = [ 0; 512 ];
+    let mut length = 0;
+    unsafe{
+        let index = POSES.len() + captured_poses;
+        captured_poses += 1;
+        length += winapi::um::winuser::wsprintfA( text.as_mut_ptr() as *mut i8, "// captured at frame %d\r\nCameraPose{ \0".as_ptr() as *const i8, ( global_scene.cut_info[ 2 ]*60.0 ) as u32 ) as usize;
+        let fields : [ ( &str, &[ f32; 4 ] ); 4 ] = [ ( "pos: [ \0", &global_scene.cam_pos ), ( "rot: [ \0", &global_scene.cam_rot ),
+                                                    ( "velocity: [ \0", &camera_velocity ), ( "rot_speed: [ \0", &camera_rot_speed ) ];
+        for ( name, values ) in &fields {
+            length += winapi::um::winuser::wsprintfA( text.as_mut_ptr().add( length ) as *mut i8, name.as_ptr() as *const i8 ) as usize;
+            let mut c = 0;
+            while c < 4 {
+                length += super::util::f32_to_text( &mut text[ length.. ], values[ c ], c < 3 );
+                c += 1;
+            }
+            length += winapi::um::winuser::wsprintfA( text.as_mut_ptr().add( length ) as *mut i8, " ], \0".as_ptr() as *const i8 ) as usize;
+        }
+        length += winapi::um::winuser::wsprintfA( text.as_mut_ptr().add( length ) as *mut i8, "},\r\n%d,   MODE_CAM_POSE | %d,\r\n\r\n\0".as_ptr() as *const i8,
+            CAPTURE_DURATION, index as u32 ) as usize;
+        super::util::append_file( "timeline.txt\0", &text[ ..length ] );
+    }
+}
+
 #[cfg(feature = "logger")]
 pub fn lbutton_up( ) {
     unsafe{ 
         moving_camera = false;
     }
-    unsafe{ super::log!( "Camera: ", global_spheres[ CAMERA_POS_IDX ][ 0 ], global_spheres[ CAMERA_POS_IDX ][ 1 ], global_spheres[ CAMERA_POS_IDX ][ 2 ]); }
+    unsafe{ super::log!( "Camera: ", global_scene.cam_pos[ 0 ], global_scene.cam_pos[ 1 ], global_scene.cam_pos[ 2 ]); }
 }
 
 static mut r3_pos : usize = 0;
@@ -128,43 +207,64 @@ fn set_r3( dest : &mut[ f32 ; 4 ], crng : &mut random::Rng, a: f32, b: f32, c: f
     dest[ 1 ] = (crng.next_f32()-offset)*b;
     dest[ 2 ] = (z-offset)*c;
     unsafe{
-        // we only ever calculate the position scaled by 512 ( by the unoffset values )
-        r3_pos = (((z*512f32) as usize *512)+(x*512f32) as usize)*4;
+        // we only ever calculate the position scaled by the terrain size ( by the unoffset values )
+        r3_pos = (((z*TERRAIN_SIZE as f32) as usize *TERRAIN_SIZE)+(x*TERRAIN_SIZE as f32) as usize)*4;
+    }
+}
+
+// Terrain height ( 0..1 ) at the position last picked by set_r3. The streamed terrain only holds the
+// cells around the camera so it is worked out from the generator instead
+fn ground_height() -> f32 {
+    unsafe{
+        #[cfg(not(feature = "tiled_terrain"))]
+        return *src_terrain.get_unchecked( r3_pos );
+        #[cfg(feature = "tiled_terrain")]
+        return TILE_GENERATOR.height( ( ( r3_pos/4 )%TERRAIN_SIZE ) as i32, ( r3_pos/4/TERRAIN_SIZE ) as i32 );
+    }
+}
+
+// Terrain surface in world units at world x,z. Outside the terrain this is the nearest edge cell
+fn world_height( x : f32, z : f32 ) -> f32 {
+    let cells = TERRAIN_SIZE as f32/512.0;
+    #[cfg(not(feature = "tiled_terrain"))]
+    {
+        let cx = if x < 0.0 { 0 } else if x*cells >= ( TERRAIN_SIZE-1 ) as f32 { TERRAIN_SIZE-1 } else { ( x*cells ) as usize };
+        let cz = if z < 0.0 { 0 } else if z*cells >= ( TERRAIN_SIZE-1 ) as f32 { TERRAIN_SIZE-1 } else { ( z*cells ) as usize };
+        unsafe{ *src_terrain.get_unchecked( ( cz*TERRAIN_SIZE + cx )*4 )*60.0-12.1 }
+    }
+    #[cfg(feature = "tiled_terrain")]
+    {
+        TILE_GENERATOR.height( terrain::floor( x*cells ), terrain::floor( z*cells ) )*60.0-12.1
     }
 }
 
-static mut sphere_delta : f32  = 0.0;
 fn set_sphere_positions(now: f32) -> ( ) {
-    let mut rng_terrain : random::Rng = random::Rng{seed: core::num::Wrapping(7923129)};
+    unsafe{
+        // the choreography keeps the spheres out of the terrain so needs the radii first
+        sphere_materials::update( &mut global_spheres );
+        choreo::update( now, &mut global_spheres, world_height );
+    }
+}
 
-    let mut idx = 0;
+static mut note_events : &'static [music::NoteEvent] = &[];
+
+// Time in seconds at which the nth ( from 0 ) note of the instrument starts. End of the intro if there is no such note
+fn note_time( instrument : u32, n : u32 ) -> f32 {
     unsafe{
-        let mut offset = math_util::sin( (now-sphere_delta)*0.02 )*sphere_scale;
-        if offset < 0.0 {
-            offset = -offset;
-        }
-        loop {
-            loop{
-                let y_offset = offset;
-                set_r3( global_spheres.get_unchecked_mut(idx*2), &mut rng_terrain,512f32,512f32,512f32, 0.0 );
-                if *src_terrain.get_unchecked( r3_pos ) > 0.3f32 {
-                    global_spheres.get_unchecked_mut(idx*2)[ 1 ] = *src_terrain.get_unchecked( r3_pos )*60.0-12.1 + y_offset;
-                    global_spheres.get_unchecked_mut(idx*2)[ 3 ] = 18.0f32;
-                    global_spheres.get_unchecked_mut(idx*2+1)[ 0 ] = FP_0_02;
-                    global_spheres.get_unchecked_mut(idx*2+1)[ 1 ] = FP_0_02;
-                    global_spheres.get_unchecked_mut(idx*2+1)[ 2 ] = FP_0_02;
-                    global_spheres.get_unchecked_mut(idx*2+1)[ 3 ] = FP_1_32;
-                    break;
+        let mut count = 0;
+        for note in note_events {
+            if note.instrument as u32 == instrument {
+                if count == n {
+                    return note.sample as f32 / music::SAMPLE_RATE as f32;
                 }
-                offset *= 1.003;
+                count += 1;
             }
-            idx += 1;
-            if idx == num_spheres { break;}
         }
     }
+    super::INTRO_DURATION as f32
 }
 
-pub fn prepare() -> () {
+pub fn prepare( notes : &'static [music::NoteEvent] ) -> () {
     let mut error_message : [i8;100] = [ 0; 100];
      let vtx_shader_src : &'static str = "#version 330 core
     layout (location = 0) in vec3 Pos;
@@ -173,9 +273,8 @@ pub fn prepare() -> () {
      gl_Position = vec4(Pos, 1.0);
     }\0";
 
-    let spheres : &mut[ [ f32; 4]; (num_spheres+sphere_extras)*2];  
     unsafe{
-        spheres  = &mut global_spheres;
+        note_events = notes;
     }
     
     let vtx_shader : u32;
@@ -184,8 +283,8 @@ pub fn prepare() -> () {
 
     #[cfg(not(feature = "logger"))]
     {
-        vtx_shader = gl_util::shader_from_source( vtx_shader_src.as_ptr(), gl::VERTEX_SHADER, &mut error_message ).unwrap();
-        frag_shader  = gl_util::shader_from_source( super::shaders::frag_shader_src.as_ptr(), gl::FRAGMENT_SHADER,  &mut error_message ).unwrap();
+        vtx_shader = gl_util::shader_from_source( &[ vtx_shader_src.as_ptr() ], gl::VERTEX_SHADER, &mut error_message ).unwrap();
+        frag_shader  = gl_util::shader_from_source( &[ scene::GLSL_HEADER.as_ptr(), super::shaders::frag_shader_src.as_ptr() ], gl::FRAGMENT_SHADER,  &mut error_message ).unwrap();
         unsafe{
             shader_prog = gl_util::program_from_shaders(vtx_shader, frag_shader, &mut error_message ).unwrap();
         }
@@ -193,13 +292,13 @@ pub fn prepare() -> () {
 
     #[cfg(feature = "logger")]
     {
-        vtx_shader = match gl_util::shader_from_source( vtx_shader_src.as_ptr(), gl::VERTEX_SHADER, &mut error_message ) {
+        vtx_shader = match gl_util::shader_from_source( &[ vtx_shader_src.as_ptr() ], gl::VERTEX_SHADER, &mut error_message ) {
             Some( shader ) => shader,
             None => { super::show_error( error_message.as_ptr()  ); 0 }
         };
         unsafe{  
             super::util::read_file( "shader.glsl\0", &mut glbl_shader_code); 
-            frag_shader  = match gl_util::shader_from_source( glbl_shader_code.as_ptr(), gl::FRAGMENT_SHADER,  &mut error_message ) {
+            frag_shader  = match gl_util::shader_from_source( &[ scene::GLSL_HEADER.as_ptr(), glbl_shader_code.as_ptr() ], gl::FRAGMENT_SHADER,  &mut error_message ) {
                 Some( shader ) => shader,
                 None => { super::show_error( error_message.as_ptr() ); 0 }
             };
@@ -212,60 +311,125 @@ pub fn prepare() -> () {
         }
     }
 
+    // Find the uniforms once, the sphere texture stays on unit 6
     unsafe{
-        super::log!( "Build terrain!");
-        // Create the terrains by dropping some lumps and randomly aggregating points around them
-        let mut rng_terrain : random::Rng = random::Rng{seed: core::num::Wrapping(7923129)};
-        let mut lumps : [[f32;4];50] = [[0f32;4];50];
-        let num_lumps = 50;
-
-        let mut nl = 0;
-        loop{
-            // do not put the lumps too close to the edges to avoid ugly discontinuities
-            set_r3( lumps.get_unchecked_mut(nl), &mut rng_terrain,0.8f32,0.8f32,0.8f32, -0.1 );
-            nl += 1;
-            if nl == num_lumps {break}
+        scene_locations = scene::Scene::locate( shader_prog );
+        num_spheres_location = gl::GetUniformLocation( shader_prog, "num_spheres\0".as_ptr() );
+        gl::UseProgram( shader_prog );
+        gl::Uniform1i( gl::GetUniformLocation( shader_prog, "spheres\0".as_ptr() ), 6 );
+    }
+
+    post::init( vtx_shader );
+    post::set_effects( &POST_EFFECTS );
+
+    #[cfg(feature = "logger")]
+    {
+        // The sequence must end together with the music
+        let mut total : f32 = 0.0;
+        let mut p = 0;
+        while p < SEQUENCE.len() {
+            if SEQUENCE[ p+1 ] & 0xf000 == MODE_SYNC_NOTE {
+                let arg = ( SEQUENCE[ p+1 ] & 0x0fff ) as u32;
+                total = note_time( arg >> 8, arg & 0xff );
+            } else {
+                total += SEQUENCE[ p ] as f32;
+            }
+            p += 2;
+        }
+        if total != super::INTRO_DURATION as f32 {
+            super::log!( "Sequence length: ", total );
+            super::show_error( "SEQUENCE length does not match INTRO_DURATION\0".as_ptr() as *const i8 );
         }
 
-        let  mut i = 0;
-        loop{
-            set_r3( spheres.get_unchecked_mut(0), &mut rng_terrain,1f32,1f32,1f32, 0.0 );
-            let x = spheres.get_unchecked_mut(0)[0];
-            let z = spheres.get_unchecked_mut(0)[2];
-
-            let mut charge = 0.0;
-            nl = 0;
-            loop{
-                let lmp = lumps.get(nl).unwrap();
-                let dist = (x-lmp[0])*(x-lmp[0]) + (z-lmp[2])*(z-lmp[2]);
-                charge += lmp[1]*0.0001/dist;
-                nl += 1;
-                if nl == num_lumps { break;}
+        // Every event argument must point at something that exists
+        p = 0;
+        while p < SEQUENCE.len() {
+            let arg = ( SEQUENCE[ p+1 ] & 0x0fff ) as usize;
+            let mode = SEQUENCE[ p+1 ] & 0xf000;
+            if mode == MODE_SPHERE_MOVE && ( ( arg & 0xff ) >= choreo::BEHAVIOURS.len() || ( ( arg >> 8 ) >= choreo::GROUPS && ( arg >> 8 ) != 0xf ) ) {
+                super::log!( "Sequence event: ", ( p/2 ) as f32 );
+                super::show_error( "MODE_SPHERE_MOVE has no such group or behaviour\0".as_ptr() as *const i8 );
+            }
+            if mode == MODE_CAM_POSE && arg >= POSES.len() {
+                super::log!( "Sequence event: ", ( p/2 ) as f32 );
+                super::show_error( "MODE_CAM_POSE has no such pose\0".as_ptr() as *const i8 );
             }
-            *src_terrain.get_unchecked_mut( r3_pos ) += charge;
-            if *src_terrain.get_unchecked( r3_pos ) > 1.0  {
-                *src_terrain.get_unchecked_mut( r3_pos ) = 1.0
+            if mode == MODE_CAM_TRACK && arg >= camera_track::TRACKS.len() {
+                super::log!( "Sequence event: ", ( p/2 ) as f32 );
+                super::show_error( "MODE_CAM_TRACK has no such track\0".as_ptr() as *const i8 );
             }
-            i += 1;
-            if i== 700_000 { break}
+            p += 2;
+        }
+
+        let mut t = 0;
+        while t < camera_track::TRACKS.len() {
+            if !camera_track::valid( &camera_track::TRACKS[ t ] ) {
+                super::log!( "Track: ", t as f32 );
+                super::show_error( "A track has no keys or a Bezier track has not got 3k+1 keys\0".as_ptr() as *const i8 );
+            }
+            t += 1;
+        }
+    }
+
+    #[cfg(feature = "terrain_bench")]
+    terrain::benchmark( &TERRAIN_GENERATOR );
+
+    // The tiles are built as the camera reaches them
+    #[cfg(not(feature = "tiled_terrain"))]
+    unsafe{
+        let mut imported = false;
+        #[cfg(feature = "terrain_io")]
+        {
+            imported = super::heightmap::import( &mut src_terrain );
         }
+        if !imported {
+            super::log!( "Build terrain!");
+            TERRAIN_GENERATOR.generate( &mut src_terrain );
+
+            // Smooth the terrain to make it less 'craggy'
+            TERRAIN_FILTER.apply( &mut src_terrain );
+        }
+        TERRAIN_GENERATOR.classify( &mut src_terrain );
+        #[cfg(feature = "terrain_io")]
+        super::heightmap::export( &src_terrain );
+    }
 
-        // Smooth the terrain once to make it less 'craggy'
-        smooth( &mut src_terrain); 
+    unsafe{
+        sphere_materials::update( &mut global_spheres );
+        choreo::init( &mut global_spheres, world_height );
     }
 
     let mut vertex_buffer_id : gl::GLuint = 0;
     unsafe{
+        // The sphere texture, on unit 6
+        let mut sphere_tex : gl::GLuint = 0;
+        gl::GenTextures( 1, &mut sphere_tex );
+        gl::ActiveTexture( gl::TEXTURE6 );
+        gl::BindTexture( gl::TEXTURE_2D, sphere_tex );
+        gl::TexImage2D( gl::TEXTURE_2D, 0, gl::RGBA32F, MAX_SPHERES as i32 * 2, 1, 0, gl::RGBA, gl::FLOAT, global_spheres.as_ptr() as *const CVoid );
+        gl::TexParameteri( gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32 );
+        gl::TexParameteri( gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32 );
+
         // Create the map texture
         gl::GenTextures( 1, &mut tex_buffer_id );
         gl::ActiveTexture(gl::TEXTURE0);
         gl::BindTexture( gl::TEXTURE_2D, tex_buffer_id );
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB, 512, 512, 0, gl::RGBA, gl::FLOAT, src_terrain.as_ptr() as *const CVoid);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB, TERRAIN_SIZE as i32, TERRAIN_SIZE as i32, 0, gl::RGBA, gl::FLOAT, src_terrain.as_ptr() as *const CVoid);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
 
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32 );
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32 );
+        #[cfg(not(feature = "tiled_terrain"))]
+        {
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32 );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32 );
+            global_scene.terrain_window = [ 0.0, 0.0, TERRAIN_SIZE as f32, TERRAIN_SIZE as f32 ];
+        }
+        // World cells wrap around the texture
+        #[cfg(feature = "tiled_terrain")]
+        {
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32 );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32 );
+        }
     }
 }
 
@@ -274,6 +438,14 @@ static mut delay_counter : i32 = 0;
 static mut play_pos : usize = 0;
 static mut camera_speed : f32 = 1.0;
 
+// A new shot starts. cut_info[ 1 ] counts the frames of the shot and times the transition into it
+fn camera_cut() {
+    unsafe{
+        global_scene.cut_info[ 1 ] = 0f32;
+    }
+    post::cut();
+}
+
 fn update_world( now: f32 ) {
     
     unsafe{
@@ -284,11 +456,48 @@ fn update_world( now: f32 ) {
         super::log!( "Camera", arg as f32, camera_mode as f32);
         if mode == MODE_CAM_PAN {
             setup_camera( arg, camera_mode as u8 );
+            active_track = None;
+            camera_cut();
+        } else if mode == MODE_CAM_POSE {
+            if let Some( pose ) = POSES.get( arg as usize ) {
+                global_scene.cam_pos = pose.pos;
+                global_scene.cam_rot = pose.rot;
+                camera_velocity = pose.velocity;
+                camera_rot_speed = pose.rot_speed;
+                active_track = None;
+                camera_cut();
+            }
+        } else if mode == MODE_CAM_ROLL {
+            global_scene.cam_rot[ 2 ] = ( ( arg >> 6 ) as f32 - 32.0 )*0.025;
+            camera_rot_speed[ 2 ] = ( ( arg & 0x3f ) as f32 - 32.0 )*0.0001;
+        } else if mode == MODE_CAM_FOV {
+            global_scene.cam_rot[ 3 ] = 0.2 + ( arg >> 6 ) as f32*0.025;
+            camera_rot_speed[ 3 ] = ( ( arg & 0x3f ) as f32 - 32.0 )*0.0002;
+        } else if mode == MODE_CAM_CLEARANCE {
+            camera_clearance = arg as f32;
+        } else if mode == MODE_CAM_TRACK {
+            let last = camera_track::TRACKS.len()-1;
+            active_track = Some( &camera_track::TRACKS[ if ( arg as usize ) < last { arg as usize } else { last } ] );
+            track_start = now;
+            camera_cut();
+        } else if mode == MODE_TRANSITION {
+            post::set_next_transition( arg >> 8, arg & 0xff );
+        } else if mode == MODE_EXPOSURE {
+            post::set_exposure( ( ( arg >> 6 ) as f32 - 32.0 )*0.125, ( ( arg & 0x3f ) as f32 - 32.0 )*0.002 );
         } else if mode == MODE_CAM_SPEED {
             camera_speed = arg as f32;
+        } else if mode == MODE_SYNC_NOTE {
+            // hold the current shot until the note starts so the next event lands on it
+            delay_counter = ((note_time( arg >> 8, arg & 0xff )-now)*60.0) as i32;
+        } else if mode == MODE_SPHERE_MOVE {
+            choreo::set_behaviour( now, arg >> 8, arg & 0xff );
+        } else if mode == MODE_SPHERE_MATERIAL {
+            sphere_materials::set_materials( arg >> 8, arg & 0xf, ( arg >> 4 ) & 0xf );
+        } else if mode == MODE_SPHERE_COUNT {
+            num_spheres = if ( arg as usize ) < choreo::GROUPS { choreo::GROUPS } else if arg as usize > MAX_SPHERES { MAX_SPHERES } else { arg as usize };
+            choreo::regroup( now, world_height );
         } else {
-            sphere_delta = now;
-            sphere_scale = arg as f32;
+            choreo::set_bounce( now, arg as f32 );
         }
         play_pos += 1;
     }
@@ -307,6 +516,65 @@ fn setup_random_camera( ) {
 }
 
 
+// Height the camera needs to be at to keep camera_clearance over the next LOOKAHEAD frames, moving dx,dz a frame
+fn clearance_height( dx : f32, dz : f32 ) -> f32 {
+    let mut need = -1.0e9;
+    let mut k = 0;
+    unsafe{
+        loop {
+            let mut h = world_height( global_scene.cam_pos[ 0 ]+dx*k as f32, global_scene.cam_pos[ 2 ]+dz*k as f32 );
+            if h < -0.5 { h = -0.5; }       // water
+            if h+camera_clearance > need { need = h+camera_clearance; }
+            k += LOOKAHEAD_STEP;
+            if k > LOOKAHEAD { break; }
+        }
+    }
+    need
+}
+
+// Lift the camera ahead of rises in the terrain on its path and steer it round the ones that are too steep
+fn avoid_terrain() {
+    unsafe{
+        let dx = camera_velocity[ 0 ]*camera_speed;
+        let dz = camera_velocity[ 2 ]*camera_speed;
+        let need = clearance_height( dx, dz );
+        let pos = &mut global_scene.cam_pos;
+        if pos[ 1 ] < need {
+            pos[ 1 ] += ( need-pos[ 1 ] )*LIFT_RATE;
+        }
+        // never closer than half the clearance, whatever is ahead
+        let floor = world_height( pos[ 0 ], pos[ 2 ] ) + camera_clearance*0.5;
+        if pos[ 1 ] < floor {
+            pos[ 1 ] = floor;
+        }
+
+        if need-pos[ 1 ] > MAX_CLIMB {
+            let ( c, s ) = ( math_util::cos( 0.3 ), math_util::sin( 0.3 ) );
+            let left = clearance_height( dx*c - dz*s, dx*s + dz*c );
+            let right = clearance_height( dx*c + dz*s, -dx*s + dz*c );
+            let turn = if left < right { TURN_RATE } else { -TURN_RATE };
+            let ( c, s ) = ( math_util::cos( turn ), math_util::sin( turn ) );
+            let x = camera_velocity[ 0 ];
+            camera_velocity[ 0 ] = x*c - camera_velocity[ 2 ]*s;
+            camera_velocity[ 2 ] = x*s + camera_velocity[ 2 ]*c;
+        }
+    }
+}
+
+// Render and score the opening frame of the pans of a range of seeds, see seed_browser.rs
+#[cfg(feature = "seed_browser")]
+pub fn browse_seeds() {
+    let mut idx = 0;
+    loop {
+        let seed = seed_browser::FIRST_SEED + idx as u32;
+        setup_camera( seed, 0 );
+        unsafe{ seed_browser::shoot( idx, seed, &global_scene.cam_pos, &global_scene.cam_rot, world_height ); }
+        idx += 1;
+        if idx == seed_browser::SEED_COUNT { break; }
+    }
+    seed_browser::write_sheet();
+}
+
 fn setup_camera( seed : u32, mode : u8) {
     unsafe{ super::log!( "Setup Camera: ", mode as f32, seed as f32 ); }
 
@@ -314,24 +582,84 @@ fn setup_camera( seed : u32, mode : u8) {
     unsafe{ super::log!( "Setup Camera: ", 2.0 ); }
     unsafe{
         super::log!( "Setup Camera: ", 11.0 );
-        set_r3( &mut global_spheres[ CAMERA_POS_IDX ], &mut crng, 512f32, 512f32, 512f32, 0.0);
-        global_spheres[ CAMERA_POS_IDX ][ 1 ] = (*src_terrain.get_unchecked( r3_pos ))*60.0-2.1+crng.next_f32()*5.0;
+        set_r3( &mut global_scene.cam_pos, &mut crng, 512f32, 512f32, 512f32, 0.0);
+        global_scene.cam_pos[ 1 ] = ground_height()*60.0-2.1+crng.next_f32()*5.0;
         super::log!( "Setup Camera: ", 12.0 );
-        set_r3( &mut global_spheres[ CAMERA_ROT_IDX ], &mut crng, FP_1_54, 3.15, FP_0_05, 0.5 );
+        set_r3( &mut global_scene.cam_rot, &mut crng, FP_1_54, 3.15, FP_0_05, 0.5 );
         set_r3( &mut camera_velocity, &mut crng, FP_0_20, FP_0_05, FP_0_20, 0.5);
         set_r3( &mut camera_rot_speed, &mut crng, 0.002, 0.001, 0.001, 0.5 );
+        // pans start level with the default lens, MODE_CAM_ROLL and MODE_CAM_FOV change them
+        global_scene.cam_rot[ 2 ] = 0.0;
+        global_scene.cam_rot[ 3 ] = camera_track::DEFAULT_FOV;
+        camera_rot_speed[ 2 ] = 0.0;
+        camera_rot_speed[ 3 ] = 0.0;
     }
     unsafe{ super::log!( "Setup Camera: ", 3.0 ); }
     
 }
 
 const MODE_CAM_PAN   : u16 = 0x1000; 
+// Plays track ( arg ) of camera_track::TRACKS from its start, until the next MODE_CAM_PAN. Past the end plays the last one
+const MODE_CAM_TRACK : u16 = 0x2000; 
 const MODE_CAM_PIVOT : u16 = 0x3000; 
 const MODE_CAM_SPEED : u16 = 0x4000; 
 const MODE_SPHERE_SCALE : u16 = 0x5000; 
+// Duration is ignored. Waits until note ( arg & 0xff ) of instrument ( arg >> 8 ) starts
+const MODE_SYNC_NOTE : u16 = 0x6000; 
+// Switches sphere group ( arg >> 8, 0xf for all of them ) to behaviour ( arg & 0xff ) of choreo::BEHAVIOURS
+// 0 bounce, 1 path, 2 flock, 3 orbit, 4 fall
+const MODE_SPHERE_MOVE : u16 = 0x7000; 
+// Gives the spheres of group ( arg >> 8, 0xf for all of them ) materials ( arg & 0xf ) and ( ( arg >> 4 ) & 0xf )
+// of sphere_materials::MATERIALS in turn
+const MODE_SPHERE_MATERIAL : u16 = 0x8000; 
+// Shows ( arg ) spheres, at least one per group and at most MAX_SPHERES. The groups are shared out again and pick
+// new loops around their spheres
+const MODE_SPHERE_COUNT : u16 = 0x9000; 
+// Puts the camera at pose ( arg ) of POSES, moving on from there like a pan. Ignored if there is no such pose
+const MODE_CAM_POSE : u16 = 0xa000; 
+// Tilts the current shot to a roll of ( ( arg >> 6 ) - 32 )*0.025 radians, rolling on by ( ( arg & 0x3f ) - 32 )*0.0001
+// radians a frame. 0x820 is level and still
+const MODE_CAM_ROLL : u16 = 0xb000; 
+// Sets the vertical field of view of the current shot to 0.2 + ( arg >> 6 )*0.025 radians, changing by
+// ( ( arg & 0x3f ) - 32 )*0.0002 radians a frame. 0x760 is close to the default lens
+const MODE_CAM_FOV : u16 = 0xc000; 
+// Keeps the camera ( arg ) world units clear of the terrain from here on, lifting it and steering it round
+// hills in its way. 0 turns it off. Tracks are left where they are put
+const MODE_CAM_CLEARANCE : u16 = 0xd000; 
+// The next camera cut ( MODE_CAM_PAN, MODE_CAM_POSE or MODE_CAM_TRACK ) blends in with post::Transition ( arg >> 8 )
+// over ( arg & 0xff ) frames. Cuts after it are hard again
+const MODE_TRANSITION : u16 = 0xe000; 
+// Sets the exposure of the tonemapping to ( ( arg >> 6 ) - 32 )*0.125 stops, changing by ( ( arg & 0x3f ) - 32 )*0.002
+// stops a frame, up to 8 stops either way. 0x820 is 0 stops and still
+const MODE_EXPOSURE : u16 = 0xf000; 
+
+// Seconds given to the events written by capture_pose
+#[cfg(feature = "logger")]
+const CAPTURE_DURATION : u32 = 10;
+
+struct CameraPose {
+    pos : [ f32; 4 ],
+    rot : [ f32; 4 ],
+    velocity : [ f32; 4 ],
+    rot_speed : [ f32; 4 ],
+}
+
+// Explicit camera poses for MODE_CAM_POSE. Press P in the logger build to add the current camera to
+// timeline.txt and paste the entries from there
+static POSES : &[ CameraPose ] = &[
+];
 
 static SEQUENCE : &[u16] = &[
 //     1200,   MODE_CAM_PAN | 1612,
+//     0,   MODE_SPHERE_MOVE | 0xf00 | 4,   // throw all the spheres up
+//     0,   MODE_SPHERE_MATERIAL | 0x245,   // group 2 alternates ember and ice glow
+//     0,   MODE_SPHERE_COUNT | 160,        // twice the spheres
+//     12,  MODE_CAM_TRACK | 0,             // sweep along the valley
+//     0,   MODE_CAM_ROLL | 0x720,          // dutch angle
+//     0,   MODE_CAM_FOV | 0x758,           // slow zoom in
+//     0,   MODE_CAM_CLEARANCE | 6,         // stay out of the hills
+//     0,   MODE_TRANSITION | 0x100 | 90,   // crossfade into the next shot over 1.5s
+//     0,   MODE_EXPOSURE | 0x824,          // slowly brighten
 // Slow pan in
 28,   MODE_CAM_PAN | 786 ,
 // Quick camera flashes
@@ -360,47 +688,73 @@ static SEQUENCE : &[u16] = &[
 4,   MODE_CAM_PAN | 2156,  // dunk down
 0,   MODE_SPHERE_SCALE | 48,
 22,   MODE_CAM_PAN | 2118,  //**
-16,   MODE_CAM_PAN | 1011,
+12,   MODE_CAM_PAN | 1011,
 
 ];
 
-pub fn frame( now : f32 ) -> () {
+// True once the last SEQUENCE event has run its full duration
+pub fn sequence_done() -> bool {
+    unsafe{ delay_counter <= 0 && play_pos*2 >= SEQUENCE.len() }
+}
+
+pub fn frame( now : f32, audio : &[[f32;4];2] ) -> () {
     set_sphere_positions(now);
 
     unsafe {
-        if delay_counter <= 0 {
+        if delay_counter <= 0 && play_pos*2 < SEQUENCE.len() {
             update_world( now );
-            global_spheres[ CAMERA_CUT_INFO ][ 1 ] = 0f32;
-
         }
         delay_counter -= 1;
-        global_spheres[ CAMERA_CUT_INFO ][ 1 ] += 1f32;
+        global_scene.cut_info[ 1 ] += 1f32;
     }
 
     unsafe{
-        // let mut dst:x86::__m128 = core::arch::x86::_mm_load_ps(global_spheres[ CAMERA_ROT_IDX ].as_mut_ptr());
+        // let mut dst:x86::__m128 = core::arch::x86::_mm_load_ps(global_scene.cam_rot.as_mut_ptr());
         // let mut src:x86::__m128 = core::arch::x86::_mm_load_ps(camera_rot_speed.as_mut_ptr());
         // dst = core::arch::x86::_mm_add_ps( dst, src);
-        // core::arch::x86::_mm_store_ss( (&mut global_spheres[ CAMERA_ROT_IDX ]).as_mut_ptr(), dst );
-        global_spheres[ CAMERA_ROT_IDX ][ 0 ] += camera_rot_speed[ 0 ]*camera_speed;
-        global_spheres[ CAMERA_ROT_IDX ][ 1 ] += camera_rot_speed[ 1 ]*camera_speed;
-        global_spheres[ CAMERA_ROT_IDX ][ 2 ] += camera_rot_speed[ 2 ]*camera_speed;
-            // dst = core::arch::x86::_mm_load_ps(global_spheres[ CAMERA_POS_IDX ].as_mut_ptr());
+        // core::arch::x86::_mm_store_ss( (&mut global_scene.cam_rot).as_mut_ptr(), dst );
+        global_scene.cam_rot[ 0 ] += camera_rot_speed[ 0 ]*camera_speed;
+        global_scene.cam_rot[ 1 ] += camera_rot_speed[ 1 ]*camera_speed;
+        global_scene.cam_rot[ 2 ] += camera_rot_speed[ 2 ]*camera_speed;
+        global_scene.cam_rot[ 3 ] += camera_rot_speed[ 3 ]*camera_speed;
+        // keep zooms between a telephoto and a wide angle that still makes sense
+        if global_scene.cam_rot[ 3 ] < 0.1 { global_scene.cam_rot[ 3 ] = 0.1; }
+        if global_scene.cam_rot[ 3 ] > 2.5 { global_scene.cam_rot[ 3 ] = 2.5; }
+            // dst = core::arch::x86::_mm_load_ps(global_scene.cam_pos.as_mut_ptr());
             // src = core::arch::x86::_mm_load_ps(camera_velocity.as_mut_ptr());
             // dst = core::arch::x86::_mm_add_ps( dst, src);
-            // core::arch::x86::_mm_store_ss( (&mut global_spheres[ CAMERA_POS_IDX ]).as_mut_ptr(), dst );
-        global_spheres[ CAMERA_POS_IDX ][ 0 ] += camera_velocity[ 0 ]*camera_speed;
-        global_spheres[ CAMERA_POS_IDX ][ 1 ] += camera_velocity[ 1 ]*camera_speed;
-        global_spheres[ CAMERA_POS_IDX ][ 2 ] += camera_velocity[ 2 ]*camera_speed;
+            // core::arch::x86::_mm_store_ss( (&mut global_scene.cam_pos).as_mut_ptr(), dst );
+        global_scene.cam_pos[ 0 ] += camera_velocity[ 0 ]*camera_speed;
+        global_scene.cam_pos[ 1 ] += camera_velocity[ 1 ]*camera_speed;
+        global_scene.cam_pos[ 2 ] += camera_velocity[ 2 ]*camera_speed;
+
+        if let Some( track ) = active_track {
+            camera_track::sample( track, now-track_start, &mut global_scene.cam_pos, &mut global_scene.cam_rot );
+        } else if camera_clearance > 0.0 {
+            avoid_terrain();
+        }
+
+        global_scene.cut_info[ 0 ] = delay_counter as f32;
+        global_scene.cut_info[ 2 ] = now;
 
-        global_spheres[ CAMERA_CUT_INFO ][ 0 ] = delay_counter as f32;
-        global_spheres[ CAMERA_CUT_INFO ][ 2 ] = now;
+        global_scene.audio_info = audio[ 0 ];
+        global_scene.audio_bands = audio[ 1 ];
+
+        #[cfg(feature = "tiled_terrain")]
+        {
+            global_scene.terrain_window = tiles::update( &TILE_GENERATOR, &mut src_terrain, global_scene.cam_pos[ 0 ], global_scene.cam_pos[ 2 ] );
+        }
     }
 
     unsafe{
         gl::UseProgram(shader_prog);
-        let shperes_loc : i32 = gl::GetUniformLocation(shader_prog, "sp\0".as_ptr());
-        gl::Uniform4fv(shperes_loc, (num_spheres+sphere_extras) as i32 * 2, transmute::<_,*const gl::GLfloat>( global_spheres.as_ptr() ) );
+        gl::ActiveTexture( gl::TEXTURE6 );
+        gl::TexSubImage2D( gl::TEXTURE_2D, 0, 0, 0, num_spheres as i32 * 2, 1, gl::RGBA, gl::FLOAT, global_spheres.as_ptr() as *const CVoid );
+        gl::ActiveTexture( gl::TEXTURE0 );
+        gl::Uniform1i( num_spheres_location, num_spheres as i32 );
+        global_scene.upload( &scene_locations );
+        post::begin_scene();
         gl::Recti( -1, -1, 1, 1 );
+        post::finish( global_scene.cut_info[ 1 ], now );
     }
 }
\ No newline at end of file
@@ -14,6 +14,20 @@ pub mod util;
 mod intro;
 mod music;
 mod random;
+mod terrain;
+mod filter;
+mod choreo;
+mod physics;
+mod sphere_materials;
+mod scene;
+mod camera_track;
+mod post;
+#[cfg(feature = "terrain_io")]
+mod heightmap;
+#[cfg(feature = "tiled_terrain")]
+mod tiles;
+#[cfg(feature = "seed_browser")]
+mod seed_browser;
 
 use core::mem::MaybeUninit;
 use core::panic::PanicInfo;
@@ -79,6 +93,8 @@ use winapi::um::winuser::{
     WS_VISIBLE,
 };
 
+// Length of the whole intro in seconds. The music and the SEQUENCE in intro.rs are both derived from / validated against this
+pub const INTRO_DURATION : usize = 120;
 
 #[cfg(not(feature = "logger"))]
 pub unsafe extern "system" fn window_proc(hwnd: HWND,
@@ -123,6 +139,11 @@ pub unsafe extern "system" fn window_proc(hwnd: HWND,
         winapi::um::winuser::WM_RBUTTONUP => {
             intro::rbutton_up();
         }
+        winapi::um::winuser::WM_KEYDOWN => {
+            if w_param == 'P' as usize {
+                intro::capture_pose();
+            }
+        }
         _ => { return DefWindowProcA(hwnd, msg, w_param, l_param); }
     }
     return 0;
@@ -289,41 +310,83 @@ pub unsafe extern fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8
 static waveFormat : winapi::shared::mmreg::WAVEFORMATEX = winapi::shared::mmreg::WAVEFORMATEX{ 
     wFormatTag : winapi::shared::mmreg::WAVE_FORMAT_IEEE_FLOAT, 
     nChannels : 1,
-    nSamplesPerSec : 44100,
-    nAvgBytesPerSec : 44100*4,
+    nSamplesPerSec : music::SAMPLE_RATE as u32,
+    nAvgBytesPerSec : (music::SAMPLE_RATE*4) as u32,
     nBlockAlign : 4,
     wBitsPerSample: 32,
     cbSize:0
  };
 
- static mut waveHeader : winapi::um::mmsystem::WAVEHDR = winapi::um::mmsystem::WAVEHDR{
+static mut waveHeaders : [winapi::um::mmsystem::WAVEHDR;2] = [winapi::um::mmsystem::WAVEHDR{
     lpData: 0 as *mut i8,
-    dwBufferLength: 44100*4*120,
+    dwBufferLength: (music::BLOCK_SAMPLES*4) as u32,
     dwBytesRecorded: 0,
     dwUser: 0,
     dwFlags: 0,
     dwLoops: 0,
     lpNext: 0 as *mut winapi::um::mmsystem::WAVEHDR,
     reserved: 0,
-};
+};2];
+
+// Double buffered music. While the device plays one block the other one is re-rendered
+static mut music_data : [[f32;music::BLOCK_SAMPLES];2] = [[0.0;music::BLOCK_SAMPLES];2];
+static mut next_block : usize = 0;
+static mut audio_features : [[f32;4];2] = [[0.0;4];2];
+
+// Refill and requeue every block the device is done with, in the order they play. After a slow frame
+// both can be done
+unsafe fn stream_music( hWaveOut : winapi::um::mmsystem::HWAVEOUT ) {
+    let mut b = 0;
+    loop {
+        let header = waveHeaders.get_unchecked_mut( next_block );
+        if header.dwFlags & winapi::um::mmsystem::WHDR_DONE == 0 {
+            break;
+        }
+        header.dwFlags &= !winapi::um::mmsystem::WHDR_DONE;
+        music::render_block( music_data.get_unchecked_mut( next_block ) );
+        winapi::um::mmeapi::waveOutWrite(hWaveOut, header, core::mem::size_of::<winapi::um::mmsystem::WAVEHDR>() as u32 );
+        next_block = 1-next_block;
+        b += 1;
+        if b == 2 {
+            break;
+        }
+    }
+}
 
-static mut music_data : [f32;44100*120] = [ 0.0;44100*120];
 #[no_mangle]
 pub extern "system" fn mainCRTStartup() {
     let ( window, hdc ) = create_window(  );
 
+    let notes = music::make_music( );
+
     unsafe{ log!("Prepare\n"); };
-    intro::prepare();
+    #[cfg(feature = "terrain_bench")]
+    let prepare_start = terrain::milliseconds();
+    intro::prepare( notes );
+    #[cfg(feature = "terrain_bench")]
+    unsafe{ log!( "Prepare ms: ", terrain::milliseconds()-prepare_start ); }
+    #[cfg(feature = "seed_browser")]
+    {
+        intro::browse_seeds();
+        unsafe{ winapi::um::processthreadsapi::ExitProcess(0); }
+    }
 
     let mut time : f32 = 0.0;
 
+    let mut hWaveOut : winapi::um::mmsystem::HWAVEOUT = 0 as winapi::um::mmsystem::HWAVEOUT;
     unsafe{
-        music::make_music( &mut music_data );
-        waveHeader.lpData = music_data.as_mut_ptr() as *mut i8;
-        let mut hWaveOut : winapi::um::mmsystem::HWAVEOUT = 0 as winapi::um::mmsystem::HWAVEOUT;
         winapi::um::mmeapi::waveOutOpen( &mut hWaveOut, winapi::um::mmsystem::WAVE_MAPPER, &waveFormat, 0, 0, winapi::um::mmsystem::CALLBACK_NULL);
-        winapi::um::mmeapi::waveOutPrepareHeader(hWaveOut, &mut waveHeader, core::mem::size_of::<winapi::um::mmsystem::WAVEHDR>() as u32 );
-        winapi::um::mmeapi::waveOutWrite(hWaveOut, &mut waveHeader, core::mem::size_of::<winapi::um::mmsystem::WAVEHDR>() as u32 );
+        let mut b = 0;
+        loop {
+            music::render_block( &mut music_data[ b ] );
+            waveHeaders[ b ].lpData = music_data[ b ].as_mut_ptr() as *mut i8;
+            winapi::um::mmeapi::waveOutPrepareHeader(hWaveOut, &mut waveHeaders[ b ], core::mem::size_of::<winapi::um::mmsystem::WAVEHDR>() as u32 );
+            winapi::um::mmeapi::waveOutWrite(hWaveOut, &mut waveHeaders[ b ], core::mem::size_of::<winapi::um::mmsystem::WAVEHDR>() as u32 );
+            b += 1;
+            if b == 2 {
+                break;
+            }
+        }
     }
 
     unsafe{ log!("Entering loop\n"); };
@@ -341,7 +404,14 @@ pub extern "system" fn mainCRTStartup() {
             }
         }
 
-        intro::frame( time );
+        unsafe{
+            stream_music( hWaveOut );
+            let mut mmt : winapi::um::mmsystem::MMTIME = core::mem::zeroed();
+            mmt.wType = winapi::um::mmsystem::TIME_SAMPLES;
+            winapi::um::mmeapi::waveOutGetPosition( hWaveOut, &mut mmt, core::mem::size_of::<winapi::um::mmsystem::MMTIME>() as u32 );
+            music::analyse( &music_data, *mmt.u.sample() as usize, &mut audio_features );
+        }
+        intro::frame( time, unsafe{ &audio_features } );
         unsafe{
             gl::UseProgram(0);
             gl::ListBase (1000); 
@@ -360,7 +430,8 @@ pub extern "system" fn mainCRTStartup() {
         unsafe{ SwapBuffers(hdc); }
         time += 1.0 / 60.0f32;  
         #[cfg(not(feature = "logger"))]
-        if time > 120.0 {
+        // ends once both the music and the SEQUENCE are over
+        if time > INTRO_DURATION as f32 && intro::sequence_done() {
             break;
         }          
     }
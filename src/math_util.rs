@@ -35,3 +35,14 @@ pub fn cos(a: f32) -> f32 {
 
     res
 }
+
+// Point t ( 0..1 ) of the way from p1 to p2 on the curve through p0..p3
+pub fn catmull_rom( p0 : f32, p1 : f32, p2 : f32, p3 : f32, t : f32 ) -> f32 {
+    0.5*( 2.0*p1 + ( p2-p0 )*t + ( 2.0*p0 - 5.0*p1 + 4.0*p2 - p3 )*t*t + ( 3.0*p1 - p0 - 3.0*p2 + p3 )*t*t*t )
+}
+
+// Point t ( 0..1 ) of the way along the cubic Bezier from p0 to p3 with control points p1, p2
+pub fn bezier( p0 : f32, p1 : f32, p2 : f32, p3 : f32, t : f32 ) -> f32 {
+    let s = 1.0-t;
+    s*s*s*p0 + 3.0*s*s*t*p1 + 3.0*s*t*t*p2 + t*t*t*p3
+}
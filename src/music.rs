@@ -1,8 +1,26 @@
 use super::random;
 
-static mut sounds : [[f32;44100*9];7] = [[0.0;44100*9];7];
+// Pitch and timing are expressed in seconds so the synth sounds the same at any rate
+#[cfg(not(any(feature = "sample_rate_48000", feature = "sample_rate_22050")))]
+pub const SAMPLE_RATE : usize = 44100;
+#[cfg(feature = "sample_rate_48000")]
+pub const SAMPLE_RATE : usize = 48000;
+#[cfg(feature = "sample_rate_22050")]
+pub const SAMPLE_RATE : usize = 22050;
+#[cfg(all(feature = "sample_rate_48000", feature = "sample_rate_22050"))]
+compile_error!( "sample_rate_48000 and sample_rate_22050 are mutually exclusive" );
+
+// Music is streamed to the wave device in blocks of this many samples
+pub const BLOCK_SAMPLES : usize = SAMPLE_RATE/2;
+const NOTE_SAMPLES : usize = SAMPLE_RATE*9;
+// Each instrument is 11 slightly detuned oscillators over 5 octaves
+const NUM_OSCILLATORS : usize = 55;
+// Notes are picked once per second. Stop early enough for the last notes to ring out before the intro ends
+const SONG_NOTES : usize = super::INTRO_DURATION - 10;
+
+static mut sounds : [[f32;NOTE_SAMPLES];7] = [[0.0;NOTE_SAMPLES];7];
 static frequencies : [ f32; 7] = [
-    349.0,     //F4   
+    349.0,     //F4
     415.0,     //Ab4            1.189
     523.0,     //C5             1.26
     554.0,     //Db5            1.059
@@ -10,26 +28,29 @@ static frequencies : [ f32; 7] = [
     698.0,     //F5             1.22
     831.0];     //Ab5
 
-fn play( dst: &mut [f32;44100*120], dst_offset : usize, signal : &[f32;44100*9], sample_duration : f32 ) {
-    let mut dst_pos = 0;
-    let mut position : f32 = 0.0;
-    unsafe{
-        loop{
-            let src_val = signal.get_unchecked(dst_pos);
-            let in_pos = position/4.5-2f32;
-            let val = (in_pos*in_pos)*position/4.5;
-            *dst.get_unchecked_mut( dst_pos + dst_offset) += src_val*val;
+// Instruments are synthesized lazily, only as far as the playing notes have reached
+static mut osc_frequency : [[f32;NUM_OSCILLATORS];7] = [[0.0;NUM_OSCILLATORS];7];
+static mut osc_position : [[f32;NUM_OSCILLATORS];7] = [[0.0;NUM_OSCILLATORS];7];
+static mut synth_pos : usize = 0;
 
-            position += sample_duration;
-            dst_pos += 1;
-            if dst_pos == 44100*9 {
-                return;
-            }
-        }
-    }
+// Bit i set in note_masks[ s ] when instrument i starts at second s
+static mut note_masks : [u8;SONG_NOTES] = [0;SONG_NOTES];
+
+// Start of a note in the song
+#[derive(Clone, Copy)]
+pub struct NoteEvent {
+    pub sample : u32,
+    pub instrument : u8,
 }
 
-pub fn make_music( music: &mut [f32;44100*120]) {
+// Every note of the song in the order they start
+static mut note_events : [NoteEvent;SONG_NOTES*7] = [NoteEvent{ sample: 0, instrument: 0};SONG_NOTES*7];
+
+// Sample position of the start of the next block to render
+static mut stream_pos : usize = 0;
+
+// Set up the instruments and pick the notes. Returns the start of every note in the song
+pub fn make_music( ) -> &'static [NoteEvent] {
     let mut vrng = random::Rng::new_unseeded();
 
     unsafe{ super::log!( "Make instruments!"); };
@@ -37,27 +58,14 @@ pub fn make_music( music: &mut [f32;44100*120]) {
     let mut i = 0;
     loop{
         let mut scale = 1.0;
+        let mut o = 0;
         // # Could combine into a single loop that doubles the scales when loop % 11 == 0. Possibly slightly shorter
         unsafe{
             loop{
                 let mut d = 0;
                 loop{
-                    let frequency : f32 = frequencies.get_unchecked(i)/scale+6.0*vrng.next_f32();
-                    let mut position : f32 = 0.0;
-                    let mut sample_no = 0;
-                    loop {
-                        let sample_duration : f32 = frequency / 44100.0f32;
-                        position = position + sample_duration;
-                        if position > 0.5 {
-                            position -= 1.0f32;
-                        }
-                        let val = core::intrinsics::fabsf32(position)*4f32-1.0f32;
-                        *sounds.get_unchecked_mut(i).get_unchecked_mut(sample_no) += val/55.0f32;
-                        sample_no += 1;
-                        if sample_no == 44100*9 {
-                            break;
-                        }
-                    }
+                    *osc_frequency.get_unchecked_mut(i).get_unchecked_mut(o) = frequencies.get_unchecked(i)/scale+6.0*vrng.next_f32();
+                    o += 1;
                     d += 1;
                     if d == 11 {
                         break;
@@ -75,28 +83,182 @@ pub fn make_music( music: &mut [f32;44100*120]) {
         }
     }
 
-    unsafe{ 
+    unsafe{
         let mut mrng : random::Rng = random::Rng{seed: core::num::Wrapping(1161249)};
-
-        let mut dst : usize = 0;
+        let mut num_events = 0;
         let mut s = 0;
         loop {
             let mut i = 0;
             loop{
                 let nt = mrng.next_f32();
                 if nt > 0.9 {
-                    play( music, dst, &sounds[i], 1.0 / 44100.0 );
+                    *note_masks.get_unchecked_mut( s ) |= 1 << i;
+                    *note_events.get_unchecked_mut( num_events ) = NoteEvent{ sample: (s*SAMPLE_RATE) as u32, instrument: i as u8 };
+                    num_events += 1;
+                }
+                i += 1;
+                if i == 7 {
+                    break;
+                }
+            }
+            s += 1;
+            if s == SONG_NOTES {
+                break;
+            }
+        }
+        &note_events[ ..num_events ]
+    }
+}
+
+fn synth_instruments( upto : usize ) {
+    unsafe{
+        let upto = if upto > NOTE_SAMPLES { NOTE_SAMPLES } else { upto };
+        if synth_pos >= upto {
+            return;
+        }
+        let mut i = 0;
+        loop{
+            let mut o = 0;
+            loop{
+                let sample_duration : f32 = osc_frequency.get_unchecked(i).get_unchecked(o) / SAMPLE_RATE as f32;
+                let mut position : f32 = *osc_position.get_unchecked(i).get_unchecked(o);
+                let mut sample_no = synth_pos;
+                loop {
+                    position = position + sample_duration;
+                    if position > 0.5 {
+                        position -= 1.0f32;
+                    }
+                    let val = core::intrinsics::fabsf32(position)*4f32-1.0f32;
+                    *sounds.get_unchecked_mut(i).get_unchecked_mut(sample_no) += val/55.0f32;
+                    sample_no += 1;
+                    if sample_no == upto {
+                        break;
+                    }
+                }
+                *osc_position.get_unchecked_mut(i).get_unchecked_mut(o) = position;
+                o += 1;
+                if o == NUM_OSCILLATORS {
+                    break;
+                }
+            }
+            i += 1;
+            if i == 7 {
+                break;
+            }
+        }
+        synth_pos = upto;
+    }
+}
+
+// Mix the part of a note that starts at note_start ( in samples ) that overlaps the block
+fn play( dst: &mut [f32;BLOCK_SAMPLES], block_start : usize, note_start : usize, signal : &[f32;NOTE_SAMPLES], sample_duration : f32 ) {
+    let mut src_pos = if block_start > note_start { block_start - note_start } else { 0 };
+    let mut dst_pos = note_start + src_pos - block_start;
+    unsafe{
+        loop{
+            if src_pos == NOTE_SAMPLES || dst_pos == BLOCK_SAMPLES {
+                return;
+            }
+            let position = src_pos as f32 * sample_duration;
+            let src_val = signal.get_unchecked(src_pos);
+            let in_pos = position/4.5-2f32;
+            let val = (in_pos*in_pos)*position/4.5;
+            *dst.get_unchecked_mut( dst_pos ) += src_val*val;
+
+            src_pos += 1;
+            dst_pos += 1;
+        }
+    }
+}
+
+// Render the next block of the song. Blocks must be rendered in order as the instruments and
+// note schedule are generated incrementally
+pub fn render_block( dst: &mut [f32;BLOCK_SAMPLES] ) {
+    unsafe{
+        let block_start = stream_pos;
+        let block_end = block_start + BLOCK_SAMPLES;
+        synth_instruments( block_end );
+
+        let mut k = 0;
+        loop {
+            dst[ k ] = 0.0;
+            k += 1;
+            if k == BLOCK_SAMPLES {
+                break;
+            }
+        }
+
+        // Every note that started within the last 9 seconds may still be ringing
+        let mut s = if block_start >= NOTE_SAMPLES { (block_start - NOTE_SAMPLES)/SAMPLE_RATE + 1 } else { 0 };
+        loop {
+            let note_start = s*SAMPLE_RATE;
+            if note_start >= block_end || s >= SONG_NOTES {
+                break;
+            }
+            let mask = *note_masks.get_unchecked( s );
+            let mut i = 0;
+            loop{
+                if mask & ( 1 << i ) != 0 {
+                    play( dst, block_start, note_start, &sounds[i], 1.0 / SAMPLE_RATE as f32 );
                 }
                 i += 1;
-                if i == 7 { 
+                if i == 7 {
                     break;
                 }
             }
-            dst += 44100;
             s += 1;
-            if s == 110 { 
-                break; 
+        }
+        stream_pos = block_end;
+    }
+}
+
+// Number of samples ahead of the play position used for the audio features
+const ANALYSIS_WINDOW : usize = 1024;
+// Centre frequencies of the bands passed to the shader
+static band_frequencies : [ f32; 4] = [ 55.0, 220.0, 440.0, 880.0 ];
+
+// Work out the audio features at sample position play_pos of the double buffered music.
+// features[ 0 ] = ( rms, note-on pulse, mask of instruments started this second, 0 )
+// features[ 1 ] = magnitudes of the band_frequencies ( single bin DFTs using Goertzel )
+pub fn analyse( data: &[[f32;BLOCK_SAMPLES];2], play_pos : usize, features : &mut [[f32;4];2] ) {
+    unsafe{
+        let mut sum = 0.0;
+        let mut b = 0;
+        loop{
+            let coeff = 2.0*super::math_util::cos( 6.2831853*band_frequencies.get_unchecked(b) / SAMPLE_RATE as f32 );
+            let mut s1 = 0.0;
+            let mut s2 = 0.0;
+            let mut k = 0;
+            loop{
+                let p = play_pos+k;
+                let val = *data.get_unchecked( (p/BLOCK_SAMPLES)%2 ).get_unchecked( p%BLOCK_SAMPLES );
+                if b == 0 {
+                    sum += val*val;
+                }
+                let s0 = val + coeff*s1 - s2;
+                s2 = s1;
+                s1 = s0;
+                k += 1;
+                if k == ANALYSIS_WINDOW {
+                    break;
+                }
+            }
+            let power = s1*s1 + s2*s2 - coeff*s1*s2;
+            features[ 1 ][ b ] = core::intrinsics::sqrtf32( if power > 0.0 { power } else { 0.0 } ) / ANALYSIS_WINDOW as f32;
+            b += 1;
+            if b == 4 {
+                break;
             }
         }
+        features[ 0 ][ 0 ] = core::intrinsics::sqrtf32( sum / ANALYSIS_WINDOW as f32 );
+
+        // Notes only ever start on whole seconds, pulse from 1 down to 0 over the second that follows
+        let s = play_pos / SAMPLE_RATE;
+        let mask = if s < SONG_NOTES { *note_masks.get_unchecked( s ) } else { 0 };
+        features[ 0 ][ 1 ] = 0.0;
+        if mask != 0 {
+            features[ 0 ][ 1 ] = 1.0 - ( play_pos - s*SAMPLE_RATE ) as f32 / SAMPLE_RATE as f32;
+        }
+        features[ 0 ][ 2 ] = mask as f32;
     }
 }
@@ -0,0 +1,126 @@
+// Contact between the spheres and the terrain as the shader draws it. cast_ray steps through the cells
+// so every cell is a flat topped column CELL world units across at the height get_height returns
+use super::choreo::Ground;
+use super::terrain::TERRAIN_SIZE;
+
+const CELL : f32 = 512.0/TERRAIN_SIZE as f32;
+// World units per frame squared
+pub const GRAVITY : f32 = 0.02;
+// Fraction of the speed into the surface kept on a bounce
+const RESTITUTION : f32 = 0.6;
+// Fraction of the speed along the surface lost each frame in contact
+const FRICTION : f32 = 0.02;
+// Slower impacts than this don't bounce, slower spheres than this come to rest
+const SETTLE_SPEED : f32 = 0.05;
+
+fn floor( v : f32 ) -> i32 {
+    let i = v as i32;
+    if ( i as f32 ) > v { i-1 } else { i }
+}
+
+fn clamp( v : f32, low : f32, high : f32 ) -> f32 {
+    if v < low { low } else if v > high { high } else { v }
+}
+
+fn sqrt( v : f32 ) -> f32 {
+    unsafe{ core::intrinsics::sqrtf32( v ) }
+}
+
+// Height of cell i,j. The ground function rounds down to the cell so ask for the middle of it
+fn column( i : i32, j : i32, ground : Ground ) -> f32 {
+    ground( ( i as f32+0.5 )*CELL, ( j as f32+0.5 )*CELL )
+}
+
+// Lowest height for the centre of a sphere of the radius at x,z that keeps it out of every column
+pub fn rest_height( x : f32, z : f32, radius : f32, ground : Ground ) -> f32 {
+    let mut height = -1.0e9;
+    let mut j = floor( ( z-radius )/CELL );
+    while j <= floor( ( z+radius )/CELL ) {
+        let mut i = floor( ( x-radius )/CELL );
+        while i <= floor( ( x+radius )/CELL ) {
+            // horizontal distance to the nearest point of the column
+            let dx = x - clamp( x, i as f32*CELL, ( i+1 ) as f32*CELL );
+            let dz = z - clamp( z, j as f32*CELL, ( j+1 ) as f32*CELL );
+            let d2 = dx*dx + dz*dz;
+            if d2 < radius*radius {
+                let y = column( i, j, ground ) + sqrt( radius*radius - d2 );
+                if y > height {
+                    height = y;
+                }
+            }
+            i += 1;
+        }
+        j += 1;
+    }
+    height
+}
+
+// Push a sphere out of the columns it overlaps and take the speed into them out of its velocity.
+// Returns how upright the total push out of the columns is ( y of its direction, 1 when it is held
+// straight up, e.g. on a flat top or between two equal steps ), below 0 if there is no contact
+pub fn collide( pos : &mut [ f32; 4 ], velocity : &mut [ f32; 4 ], radius : f32, ground : Ground ) -> f32 {
+    let mut push = [ 0.0; 3 ];
+    let mut touching = false;
+    let mut j = floor( ( pos[ 2 ]-radius )/CELL );
+    while j <= floor( ( pos[ 2 ]+radius )/CELL ) {
+        let mut i = floor( ( pos[ 0 ]-radius )/CELL );
+        while i <= floor( ( pos[ 0 ]+radius )/CELL ) {
+            let top = column( i, j, ground );
+            // nearest point of the column to the centre
+            let d = [ pos[ 0 ] - clamp( pos[ 0 ], i as f32*CELL, ( i+1 ) as f32*CELL ),
+                      pos[ 1 ] - if pos[ 1 ] > top { top } else { pos[ 1 ] },
+                      pos[ 2 ] - clamp( pos[ 2 ], j as f32*CELL, ( j+1 ) as f32*CELL ) ];
+            let d2 = d[ 0 ]*d[ 0 ] + d[ 1 ]*d[ 1 ] + d[ 2 ]*d[ 2 ];
+            if d2 < radius*radius {
+                // a centre inside the column goes out through the top
+                let ( normal, depth ) = if d2 > 0.0 {
+                    let dist = sqrt( d2 );
+                    ( [ d[ 0 ]/dist, d[ 1 ]/dist, d[ 2 ]/dist ], radius-dist )
+                } else {
+                    ( [ 0.0, 1.0, 0.0 ], top-pos[ 1 ]+radius )
+                };
+                touching = true;
+                push[ 0 ] += normal[ 0 ]*depth;
+                push[ 1 ] += normal[ 1 ]*depth;
+                push[ 2 ] += normal[ 2 ]*depth;
+                pos[ 0 ] += normal[ 0 ]*depth;
+                pos[ 1 ] += normal[ 1 ]*depth;
+                pos[ 2 ] += normal[ 2 ]*depth;
+
+                let into = velocity[ 0 ]*normal[ 0 ] + velocity[ 1 ]*normal[ 1 ] + velocity[ 2 ]*normal[ 2 ];
+                if into < 0.0 {
+                    let bounce = if -into < SETTLE_SPEED { 1.0 } else { 1.0+RESTITUTION };
+                    velocity[ 0 ] -= normal[ 0 ]*into*bounce;
+                    velocity[ 1 ] -= normal[ 1 ]*into*bounce;
+                    velocity[ 2 ] -= normal[ 2 ]*into*bounce;
+                }
+            }
+            i += 1;
+        }
+        j += 1;
+    }
+    if !touching {
+        return -1.0;
+    }
+    let length = sqrt( push[ 0 ]*push[ 0 ] + push[ 1 ]*push[ 1 ] + push[ 2 ]*push[ 2 ] );
+    if length > 0.0 { push[ 1 ]/length } else { 0.0 }
+}
+
+// Move a sphere on by a frame under gravity. Rolls off edges and down the steps of the terrain and
+// comes to rest once it is slow enough on flat ground
+pub fn step( pos : &mut [ f32; 4 ], velocity : &mut [ f32; 4 ], radius : f32, ground : Ground ) {
+    velocity[ 1 ] -= GRAVITY;
+    pos[ 0 ] += velocity[ 0 ];
+    pos[ 1 ] += velocity[ 1 ];
+    pos[ 2 ] += velocity[ 2 ];
+    let support = collide( pos, velocity, radius, ground );
+    if support >= 0.0 {
+        velocity[ 0 ] *= 1.0-FRICTION;
+        velocity[ 2 ] *= 1.0-FRICTION;
+        if support > 0.99 && velocity[ 0 ]*velocity[ 0 ] + velocity[ 1 ]*velocity[ 1 ] + velocity[ 2 ]*velocity[ 2 ] < SETTLE_SPEED*SETTLE_SPEED {
+            velocity[ 0 ] = 0.0;
+            velocity[ 1 ] = 0.0;
+            velocity[ 2 ] = 0.0;
+        }
+    }
+}
@@ -0,0 +1,352 @@
+// Full screen passes after the scene. The scene is drawn in linear HDR colour into scene_tex and copied to the
+// screen through the post shader, which tonemaps it, blends it with the last frame before a camera cut for the
+// transitions and applies the Effects. Bloom blurs the highlights of the scene into bloom_tex first, across
+// into blur_tex then down
+use super::gl;
+use super::gl_util;
+use super::scene;
+
+use gl::CVoid;
+
+pub const WIDTH : i32 = 1920;
+pub const HEIGHT : i32 = 1080;
+
+// How the shot after a camera cut comes in. The numbers are the ones used by MODE_TRANSITION and the shader
+#[derive(Clone, Copy)]
+pub enum Transition {
+    Cut = 0,
+    Crossfade = 1,
+    FadeBlack = 2,
+    Flash = 3,
+    Wipe = 4,
+}
+
+static TRANSITIONS : [ Transition; 5 ] = [ Transition::Cut, Transition::Crossfade, Transition::FadeBlack, Transition::Flash, Transition::Wipe ];
+
+// Maps the HDR colour of the scene to the screen. The numbers are the ones used by the shader
+#[derive(Clone, Copy)]
+pub enum Tonemap {
+    // cuts off at 1 like the scene did before it was HDR
+    Clip = 0,
+    Reinhard = 1,
+    Aces = 2,
+    Filmic = 3,
+}
+
+// Everything above the threshold is blurred and added back on, the specular highlights and glowing spheres
+pub struct Bloom {
+    pub threshold : f32,
+    pub intensity : f32,
+    // in pixels, about the reach of the blur
+    pub radius : f32,
+}
+
+// Darkens from inner to outer, distances from the centre in screen heights
+pub struct Vignette {
+    pub strength : f32,
+    pub inner : f32,
+    pub outer : f32,
+}
+
+pub struct Grain {
+    // of the noise added to each channel
+    pub amount : f32,
+}
+
+// Baked into the LUT, in this order: lift and gain of each channel, contrast 0..1 as an S curve, saturation
+// and a tint blended from shadows to highlights. amount blends from the ungraded colour
+pub struct Grade {
+    pub lift : [ f32; 3 ],
+    pub gain : [ f32; 3 ],
+    pub contrast : f32,
+    pub saturation : f32,
+    pub shadows : [ f32; 3 ],
+    pub highlights : [ f32; 3 ],
+    pub amount : f32,
+}
+
+// None switches an effect off
+pub struct Effects {
+    pub tonemap : Tonemap,
+    // in stops, MODE_EXPOSURE changes it from the sequence
+    pub exposure : f32,
+    pub bloom : Option< Bloom >,
+    pub vignette : Option< Vignette >,
+    pub grain : Option< Grain >,
+    pub grade : Option< Grade >,
+}
+
+pub static NO_EFFECTS : Effects = Effects{ tonemap: Tonemap::Clip, exposure: 0.0, bloom: None, vignette: None, grain: None, grade: None };
+
+const LUT_SIZE : usize = 16;
+// stops either way
+const MAX_EXPOSURE : f32 = 8.0;
+
+static mut program : gl::GLuint = 0;
+static mut bloom_program : gl::GLuint = 0;
+static mut scene_fbo : gl::GLuint = 0;
+static mut bloom_fbo : gl::GLuint = 0;
+static mut blur_fbo : gl::GLuint = 0;
+// on texture unit 1
+static mut scene_tex : gl::GLuint = 0;
+// on texture unit 2
+static mut previous_tex : gl::GLuint = 0;
+// on texture unit 3
+static mut bloom_tex : gl::GLuint = 0;
+// on texture unit 4
+static mut blur_tex : gl::GLuint = 0;
+// on texture unit 5
+static mut lut_tex : gl::GLuint = 0;
+static mut params : scene::Post = scene::POST_EMPTY;
+static mut locations : scene::PostLocations = scene::PostLocations::UNLOCATED;
+static mut bloom_locations : scene::PostLocations = scene::PostLocations::UNLOCATED;
+static mut bloom_source : gl::GLint = 0;
+static mut effects : &Effects = &NO_EFFECTS;
+// stops a frame
+static mut exposure_speed : f32 = 0.0;
+static mut lut : [ u8; LUT_SIZE*LUT_SIZE*LUT_SIZE*4 ] = [ 0; LUT_SIZE*LUT_SIZE*LUT_SIZE*4 ];
+
+// Used by the next camera cut, then back to a hard cut
+static mut next_transition : ( Transition, u32 ) = ( Transition::Cut, 0 );
+// Playing since the last camera cut
+static mut transition : ( Transition, u32 ) = ( Transition::Cut, 0 );
+
+#[cfg(feature = "logger")]
+static mut post_shader_code : [ u8; 8000 ] = [ 0; 8000 ];
+#[cfg(feature = "logger")]
+static mut bloom_shader_code : [ u8; 8000 ] = [ 0; 8000 ];
+
+fn screen_texture( unit : gl::GLenum, tex : &mut gl::GLuint, format : gl::GLenum, width : i32, height : i32 ) {
+    unsafe{
+        gl::GenTextures( 1, tex );
+        gl::ActiveTexture( unit );
+        gl::BindTexture( gl::TEXTURE_2D, *tex );
+        gl::TexImage2D( gl::TEXTURE_2D, 0, format, width, height, 0, gl::RGBA, if format == gl::RGBA8 { gl::UNSIGNED_BYTE } else { gl::FLOAT }, 0 as *const CVoid );
+        gl::TexParameteri( gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32 );
+        gl::TexParameteri( gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32 );
+        gl::TexParameteri( gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32 );
+        gl::TexParameteri( gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32 );
+    }
+}
+
+fn render_target( tex : gl::GLuint, fbo : &mut gl::GLuint ) {
+    unsafe{
+        gl::GenFramebuffers( 1, fbo );
+        gl::BindFramebuffer( gl::FRAMEBUFFER, *fbo );
+        gl::FramebufferTexture2D( gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, tex, 0 );
+    }
+}
+
+// Program of the vertex shader of the scene and a fragment shader after scene::POST_GLSL_HEADER. The fragment
+// shader is src, the logger build reads it from file_name into code
+fn post_program( vtx_shader : gl::GLuint,
+        #[cfg(not(feature = "logger"))] src : &'static str,
+        #[cfg(feature = "logger")] file_name : &str,
+        #[cfg(feature = "logger")] code : &mut [ u8 ] ) -> gl::GLuint {
+    let mut error_message : [i8;100] = [ 0; 100];
+    let frag_shader : gl::GLuint;
+    let prog : gl::GLuint;
+
+    #[cfg(not(feature = "logger"))]
+    {
+        frag_shader = gl_util::shader_from_source( &[ scene::POST_GLSL_HEADER.as_ptr(), src.as_ptr() ], gl::FRAGMENT_SHADER, &mut error_message ).unwrap();
+        prog = gl_util::program_from_shaders( vtx_shader, frag_shader, &mut error_message ).unwrap();
+    }
+
+    #[cfg(feature = "logger")]
+    {
+        unsafe{ super::util::read_file( file_name, code ); }
+        frag_shader = match gl_util::shader_from_source( &[ scene::POST_GLSL_HEADER.as_ptr(), code.as_ptr() ], gl::FRAGMENT_SHADER, &mut error_message ) {
+            Some( shader ) => shader,
+            None => { super::show_error( error_message.as_ptr() ); 0 }
+        };
+        prog = match gl_util::program_from_shaders( vtx_shader, frag_shader, &mut error_message ) {
+            Some( prog ) => prog,
+            None => { super::show_error( error_message.as_ptr() ); 0 }
+        };
+    }
+    prog
+}
+
+// Build the post shaders with the vertex shader of the scene and the render targets
+pub fn init( vtx_shader : gl::GLuint ) {
+    unsafe{
+        #[cfg(not(feature = "logger"))]
+        {
+            program = post_program( vtx_shader, super::shaders::post_shader_src );
+            bloom_program = post_program( vtx_shader, super::shaders::bloom_shader_src );
+        }
+        #[cfg(feature = "logger")]
+        {
+            program = post_program( vtx_shader, "post.glsl\0", &mut post_shader_code );
+            bloom_program = post_program( vtx_shader, "bloom.glsl\0", &mut bloom_shader_code );
+        }
+        locations = scene::Post::locate( program );
+        bloom_locations = scene::Post::locate( bloom_program );
+        bloom_source = gl::GetUniformLocation( bloom_program, "source\0".as_ptr() );
+
+        // HDR, the scene goes over 1 in the highlights and the sky
+        screen_texture( gl::TEXTURE1, &mut scene_tex, gl::RGBA16F, WIDTH, HEIGHT );
+        screen_texture( gl::TEXTURE2, &mut previous_tex, gl::RGBA16F, WIDTH, HEIGHT );
+        screen_texture( gl::TEXTURE3, &mut bloom_tex, gl::RGBA16F, WIDTH, HEIGHT );
+        screen_texture( gl::TEXTURE4, &mut blur_tex, gl::RGBA16F, WIDTH, HEIGHT );
+        // the slices of the blue channel side by side
+        screen_texture( gl::TEXTURE5, &mut lut_tex, gl::RGBA8, ( LUT_SIZE*LUT_SIZE ) as i32, LUT_SIZE as i32 );
+        render_target( scene_tex, &mut scene_fbo );
+        render_target( bloom_tex, &mut bloom_fbo );
+        render_target( blur_tex, &mut blur_fbo );
+        gl::BindFramebuffer( gl::FRAMEBUFFER, 0 );
+
+        gl::UseProgram( program );
+        gl::Uniform1i( gl::GetUniformLocation( program, "frame\0".as_ptr() ), 1 );
+        gl::Uniform1i( gl::GetUniformLocation( program, "previous\0".as_ptr() ), 2 );
+        gl::Uniform1i( gl::GetUniformLocation( program, "bloom_map\0".as_ptr() ), 4 );
+        gl::Uniform1i( gl::GetUniformLocation( program, "lut\0".as_ptr() ), 5 );
+        // the terrain texture is updated on unit 0
+        gl::ActiveTexture( gl::TEXTURE0 );
+    }
+}
+
+fn clamp01( v : f32 ) -> f32 {
+    if v < 0.0 { 0.0 } else if v > 1.0 { 1.0 } else { v }
+}
+
+// Fill the LUT with the grade
+fn bake_lut( grade : &Grade ) {
+    unsafe{
+        let mut b = 0;
+        while b < LUT_SIZE {
+            let mut g = 0;
+            while g < LUT_SIZE {
+                let mut r = 0;
+                while r < LUT_SIZE {
+                    let mut c = [ r as f32/( LUT_SIZE-1 ) as f32, g as f32/( LUT_SIZE-1 ) as f32, b as f32/( LUT_SIZE-1 ) as f32 ];
+                    let mut i = 0;
+                    while i < 3 {
+                        let v = grade.lift[ i ] + c[ i ]*( grade.gain[ i ]-grade.lift[ i ] );
+                        let s = clamp01( v );
+                        c[ i ] = v + ( s*s*( 3.0-2.0*s ) - v )*grade.contrast;
+                        i += 1;
+                    }
+                    let luma = c[ 0 ]*0.2126 + c[ 1 ]*0.7152 + c[ 2 ]*0.0722;
+                    let tint = clamp01( luma );
+                    let p = ( ( g*LUT_SIZE + b )*LUT_SIZE + r )*4;
+                    let mut i = 0;
+                    while i < 3 {
+                        let v = ( luma + ( c[ i ]-luma )*grade.saturation )*( grade.shadows[ i ] + ( grade.highlights[ i ]-grade.shadows[ i ] )*tint );
+                        lut[ p+i ] = ( clamp01( v )*255.0 + 0.5 ) as u8;
+                        i += 1;
+                    }
+                    lut[ p+3 ] = 255;
+                    r += 1;
+                }
+                g += 1;
+            }
+            b += 1;
+        }
+        gl::ActiveTexture( gl::TEXTURE5 );
+        gl::TexImage2D( gl::TEXTURE_2D, 0, gl::RGBA8, ( LUT_SIZE*LUT_SIZE ) as i32, LUT_SIZE as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, lut.as_ptr() as *const CVoid );
+        gl::ActiveTexture( gl::TEXTURE0 );
+    }
+}
+
+// Switch to a set of effects, e.g. per part of the intro
+pub fn set_effects( new_effects : &'static Effects ) {
+    unsafe{
+        effects = new_effects;
+        params.tonemap = [ effects.tonemap as u32 as f32, effects.exposure, 0.0, 0.0 ];
+        exposure_speed = 0.0;
+        params.bloom = match &effects.bloom {
+            Some( bloom ) => [ bloom.threshold, bloom.intensity, 0.0, 0.0 ],
+            None => [ 0.0; 4 ],
+        };
+        params.vignette = match &effects.vignette {
+            Some( vignette ) => [ vignette.strength, vignette.inner, vignette.outer, 0.0 ],
+            None => [ 0.0; 4 ],
+        };
+        params.grain[ 0 ] = match &effects.grain {
+            Some( grain ) => grain.amount,
+            None => 0.0,
+        };
+        params.grade[ 0 ] = match &effects.grade {
+            Some( grade ) => { bake_lut( grade ); grade.amount },
+            None => 0.0,
+        };
+    }
+}
+
+// Transition ( kind ) of TRANSITIONS over ( frames ) frames for the next camera cut. Kinds past the end are a Cut
+pub fn set_next_transition( kind : u32, frames : u32 ) {
+    let kind = if ( kind as usize ) < TRANSITIONS.len() { TRANSITIONS[ kind as usize ] } else { Transition::Cut };
+    unsafe{
+        next_transition = ( kind, frames );
+    }
+}
+
+// Called on a camera cut before the first frame of the new shot is drawn. Keeps the last frame of the old
+// shot if the cut has a transition
+pub fn cut() {
+    unsafe{
+        transition = next_transition;
+        next_transition = ( Transition::Cut, 0 );
+        if let Transition::Cut = transition.0 {
+            return;
+        }
+        gl::BindFramebuffer( gl::FRAMEBUFFER, scene_fbo );
+        gl::ActiveTexture( gl::TEXTURE2 );
+        gl::CopyTexSubImage2D( gl::TEXTURE_2D, 0, 0, 0, 0, 0, WIDTH, HEIGHT );
+        gl::ActiveTexture( gl::TEXTURE0 );
+        gl::BindFramebuffer( gl::FRAMEBUFFER, 0 );
+    }
+}
+
+// The scene is drawn into scene_tex from here on
+pub fn begin_scene() {
+    unsafe{
+        gl::BindFramebuffer( gl::FRAMEBUFFER, scene_fbo );
+    }
+}
+
+// Set the exposure to stops, changing by speed stops a frame
+pub fn set_exposure( stops : f32, speed : f32 ) {
+    unsafe{
+        params.tonemap[ 1 ] = stops;
+        exposure_speed = speed;
+    }
+}
+
+// Draw scene_tex to the screen. frames_since_cut is cut_info[ 1 ]
+pub fn finish( frames_since_cut : f32, now : f32 ) {
+    unsafe{
+        let ( kind, frames ) = transition;
+        let mut progress = if frames == 0 { 1.0 } else { frames_since_cut/frames as f32 };
+        if progress > 1.0 { progress = 1.0; }
+        params.transition = [ kind as u32 as f32, progress, 0.0, 0.0 ];
+        params.grain[ 1 ] = now;
+        params.tonemap[ 1 ] += exposure_speed;
+        // keep a drifting exposure from burning out or going black
+        if params.tonemap[ 1 ] < -MAX_EXPOSURE { params.tonemap[ 1 ] = -MAX_EXPOSURE; }
+        if params.tonemap[ 1 ] > MAX_EXPOSURE { params.tonemap[ 1 ] = MAX_EXPOSURE; }
+
+        if let Some( bloom ) = &effects.bloom {
+            // 9 taps, 4 steps either side
+            let step = bloom.radius*0.25;
+            gl::UseProgram( bloom_program );
+            gl::BindFramebuffer( gl::FRAMEBUFFER, bloom_fbo );
+            gl::Uniform1i( bloom_source, 1 );
+            params.blur = [ step/WIDTH as f32, 0.0, 1.0, 0.0 ];
+            params.upload( &bloom_locations );
+            gl::Recti( -1, -1, 1, 1 );
+            gl::BindFramebuffer( gl::FRAMEBUFFER, blur_fbo );
+            gl::Uniform1i( bloom_source, 3 );
+            params.blur = [ 0.0, step/HEIGHT as f32, 0.0, 0.0 ];
+            params.upload( &bloom_locations );
+            gl::Recti( -1, -1, 1, 1 );
+        }
+
+        gl::BindFramebuffer( gl::FRAMEBUFFER, 0 );
+        gl::UseProgram( program );
+        params.upload( &locations );
+        gl::Recti( -1, -1, 1, 1 );
+    }
+}
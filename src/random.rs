@@ -17,6 +17,21 @@ impl Rng {
         self.seed.0
     }
 
+    // Advance the generator by n steps in log(n) time, as if next_u32 had been called n times
+    pub fn skip(&mut self, n: u32) {
+        let mut mul = Wrapping(214013u32);
+        let mut add = Wrapping(2531011u32);
+        let mut n = n;
+        while n > 0 {
+            if n & 1 != 0 {
+                self.seed = self.seed*mul + add;
+            }
+            add = add*mul + add;
+            mul = mul*mul;
+            n >>= 1;
+        }
+    }
+
     pub fn next_f32(&mut self) -> f32 {
         const UPPER_MASK: u32 = 0x3F800000;
         const LOWER_MASK: u32 = 0x7FFFFF;
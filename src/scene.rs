@@ -0,0 +1,104 @@
+// The uniforms the shaders read. uniform_block! declares a struct, the names to upload it by, a struct for their
+// locations in a program and the GLSL declarations from one list so the Rust side and the shaders can't disagree.
+// The GLSL goes in front of the shader source when it is compiled
+use super::gl;
+
+macro_rules! uniform_block {
+    ( $block:ident, $locations:ident, $empty:ident, $header:ident = $prefix:expr; $( $name:ident ),* $(,)? ) => {
+        // One vec4 per field
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct $block {
+            $( pub $name : [ f32; 4 ], )*
+        }
+
+        pub const $empty : $block = $block{ $( $name: [ 0.0; 4 ], )* };
+
+        pub static $header : &str = concat!( $prefix, $( "uniform vec4 ", stringify!( $name ), ";", )* "\n\0" );
+
+        // Where the fields are in one program, looked up once after it is linked
+        pub struct $locations {
+            locations : [ gl::GLint; $block::NAMES.len() ],
+        }
+
+        impl $locations {
+            pub const UNLOCATED : $locations = $locations{ locations: [ -1; $block::NAMES.len() ] };
+        }
+
+        impl $block {
+            const NAMES : &'static [ &'static str ] = &[ $( concat!( stringify!( $name ), "\0" ), )* ];
+
+            // Look up every field in a linked program
+            pub fn locate( program : gl::GLuint ) -> $locations {
+                let mut found = $locations::UNLOCATED;
+                locate( program, &mut found.locations, Self::NAMES );
+                found
+            }
+
+            // Set every field on the program in use, found there by locate
+            pub fn upload( &self, found : &$locations ) {
+                upload( &found.locations, self as *const $block as *const [ f32; 4 ] );
+            }
+        }
+    };
+}
+
+// The spheres are too many for the uniforms so they go in a float texture, two texels per sphere, which the
+// shader reads with sp( i )
+pub const MAX_SPHERES : usize = 256;
+
+// Everything in the main shader but the spheres
+uniform_block!{ Scene, SceneLocations, EMPTY, GLSL_HEADER = "#version 330 core\nuniform sampler2D spheres;uniform int num_spheres;vec4 sp(int i){return texelFetch(spheres,ivec2(i,0),0);}";
+    cam_pos,
+    // ( pitch, yaw, roll, vertical field of view )
+    cam_rot,
+    // ( frames to the next event, frames since the last camera cut, time, 0 )
+    cut_info,
+    // ( audio rms, note-on pulse, started instruments, 0 )
+    audio_info,
+    // audio band magnitudes
+    audio_bands,
+    // ( min x, min z, max x, max z ) of the terrain cells held by the terrain texture
+    terrain_window,
+}
+
+// The post shaders. frame is the scene, previous the last frame of the shot before the cut, source the input of
+// a bloom pass, bloom_map the blurred highlights and lut the colour grade
+uniform_block!{ Post, PostLocations, POST_EMPTY, POST_GLSL_HEADER = "#version 330 core\nuniform sampler2D frame,previous,source,bloom_map,lut;";
+    // ( post::Transition, 0..1 of the way through it, 0, 0 )
+    transition,
+    // ( post::Tonemap, exposure in stops, 0, 0 )
+    tonemap,
+    // ( threshold, intensity, 0, 0 ), intensity 0 is off
+    bloom,
+    // ( x step, y step, 1 on the bright pass, 0 ) of a bloom pass
+    blur,
+    // ( strength, inner radius, outer radius, 0 ), strength 0 is off
+    vignette,
+    // ( amount, time, 0, 0 )
+    grain,
+    // ( amount of the graded colour, 0, 0, 0 )
+    grade,
+}
+
+fn locate( program : gl::GLuint, locations : &mut [ gl::GLint ], names : &[ &str ] ) {
+    unsafe{
+        let mut idx = 0;
+        loop {
+            locations[ idx ] = gl::GetUniformLocation( program, names[ idx ].as_ptr() );
+            idx += 1;
+            if idx == names.len() { break; }
+        }
+    }
+}
+
+fn upload( locations : &[ gl::GLint ], values : *const [ f32; 4 ] ) {
+    unsafe{
+        let mut idx = 0;
+        loop {
+            gl::Uniform4fv( locations[ idx ], 1, ( *values.add( idx ) ).as_ptr() );
+            idx += 1;
+            if idx == locations.len() { break; }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Browser for the seeds of MODE_CAM_PAN. Development only. Renders the opening frame of the shot of every
+// seed in FIRST_SEED..FIRST_SEED+SEED_COUNT on the CPU ( terrain and water, no spheres ), scores it and writes
+//
+// seeds.bmp   contact sheet of the shots, best score first, COLUMNS to a row
+// seeds.txt   the seeds and scores in the same order
+//
+// The score is the product of how close the terrain coverage is to COVERAGE_GOAL and how close the horizon
+// is to HORIZON_GOAL of the way down the frame. Shots starting inside the terrain or under water score 0
+use super::choreo::Ground;
+use super::math_util;
+use super::util;
+
+pub const FIRST_SEED : u32 = 1900;
+pub const SEED_COUNT : usize = 256;
+
+const THUMB_W : usize = 64;
+const THUMB_H : usize = 36;
+const COLUMNS : usize = 16;
+const GAP : usize = 2;
+const SHEET_W : usize = COLUMNS*( THUMB_W+GAP )+GAP;
+const SHEET_H : usize = ( SEED_COUNT+COLUMNS-1 )/COLUMNS*( THUMB_H+GAP )+GAP;
+// BMP rows are padded to 4 bytes
+const SHEET_STRIDE : usize = ( SHEET_W*3+3 ) & !3;
+
+// Fraction of the frame that should be terrain
+const COVERAGE_GOAL : f32 = 0.6;
+// Height of the horizon from the top of the frame, a third for the rule of thirds
+const HORIZON_GOAL : f32 = 0.333;
+
+const FAR : f32 = 600.0;
+// Same as the shader
+const WATER_LEVEL : f32 = -0.5;
+const WIDTH : f32 = 1920.0;
+const HEIGHT : f32 = 1080.0;
+
+const SKY : [ f32; 3 ] = [ 0.55, 0.7, 0.9 ];
+const WATER : [ f32; 3 ] = [ 0.05, 0.1, 0.25 ];
+
+static mut thumbnails : [ u8; SEED_COUNT*THUMB_W*THUMB_H*3 ] = [ 0; SEED_COUNT*THUMB_W*THUMB_H*3 ];
+// ( seed, score, thumbnail ) of every shot
+static mut shots : [ ( u32, f32, usize ); SEED_COUNT ] = [ ( 0, 0.0, 0 ); SEED_COUNT ];
+static mut file_buffer : [ u8; 54 + SHEET_STRIDE*SHEET_H ] = [ 0; 54 + SHEET_STRIDE*SHEET_H ];
+
+fn sqrt( v : f32 ) -> f32 {
+    unsafe{ core::intrinsics::sqrtf32( v ) }
+}
+
+fn normalize( v : [ f32; 3 ] ) -> [ f32; 3 ] {
+    let length = sqrt( v[ 0 ]*v[ 0 ] + v[ 1 ]*v[ 1 ] + v[ 2 ]*v[ 2 ] );
+    [ v[ 0 ]/length, v[ 1 ]/length, v[ 2 ]/length ]
+}
+
+fn mix( a : [ f32; 3 ], b : [ f32; 3 ], f : f32 ) -> [ f32; 3 ] {
+    [ a[ 0 ]+( b[ 0 ]-a[ 0 ] )*f, a[ 1 ]+( b[ 1 ]-a[ 1 ] )*f, a[ 2 ]+( b[ 2 ]-a[ 2 ] )*f ]
+}
+
+fn to_u8( v : f32 ) -> u8 {
+    if v <= 0.0 { 0 } else if v >= 1.0 { 255 } else { ( v*255.0 ) as u8 }
+}
+
+// Colour seen along dir from origin and whether the ray hit the terrain ( 1 ), the water ( 2 ) or nothing ( 0 )
+fn trace( origin : &[ f32; 4 ], dir : [ f32; 3 ], ground : Ground ) -> ( [ f32; 3 ], u8 ) {
+    let sun = normalize( [ 1.0, 1.1, 1.0 ] );
+    let mut t = 0.5;
+    while t < FAR {
+        let x = origin[ 0 ]+dir[ 0 ]*t;
+        let y = origin[ 1 ]+dir[ 1 ]*t;
+        let z = origin[ 2 ]+dir[ 2 ]*t;
+        let h = ground( x, z );
+        if y < h || y < WATER_LEVEL {
+            let fog = t/FAR;
+            if h < WATER_LEVEL {
+                return ( mix( WATER, SKY, fog ), 2 );
+            }
+            let n = normalize( [ ground( x-1.0, z )-ground( x+1.0, z ), 2.0, ground( x, z-1.0 )-ground( x, z+1.0 ) ] );
+            let mut light = n[ 0 ]*sun[ 0 ] + n[ 1 ]*sun[ 1 ] + n[ 2 ]*sun[ 2 ];
+            if light < 0.0 { light = 0.0; }
+            // brown low down, grey on the peaks
+            let base = mix( [ 0.2, 0.15, 0.06 ], [ 0.45, 0.45, 0.48 ], if h > 40.0 { 1.0 } else if h < 0.0 { 0.0 } else { h/40.0 } );
+            let lit = [ base[ 0 ]*( 0.3+light ), base[ 1 ]*( 0.3+light ), base[ 2 ]*( 0.3+light ) ];
+            return ( mix( lit, SKY, fog ), 1 );
+        }
+        t += 0.25 + t*0.01;
+    }
+    // lighter towards the horizon
+    let haze = 1.0-dir[ 1 ]*2.0;
+    ( mix( SKY, [ 1.0, 1.0, 1.0 ], if haze > 1.0 { 1.0 } else if haze < 0.0 { 0.0 } else { haze } ), 0 )
+}
+
+// Render and score the shot of a seed starting from the camera at pos, rot. idx is the number of the seed
+// in the range
+pub fn shoot( idx : usize, seed : u32, pos : &[ f32; 4 ], rot : &[ f32; 4 ], ground : Ground ) {
+    // the rotation of the shader
+    let ( cx, sx, cy, sy ) = ( math_util::cos( rot[ 0 ] ), math_util::sin( rot[ 0 ] ), math_util::cos( rot[ 1 ] ), math_util::sin( rot[ 1 ] ) );
+    let mut terrain = 0;
+    let mut horizon = 0.0;
+    unsafe{
+        let pixels = &mut thumbnails[ idx*THUMB_W*THUMB_H*3 .. ( idx+1 )*THUMB_W*THUMB_H*3 ];
+        let mut x = 0;
+        loop {
+            // row of the highest ground in the column
+            let mut top = THUMB_H;
+            let mut y = 0;
+            loop {
+                let u = 2.0*( x as f32+0.5 )/THUMB_H as f32 - WIDTH/HEIGHT;
+                let v = 1.0 - 2.0*( y as f32+0.5 )/THUMB_H as f32;
+                let dir = normalize( [ cy*u - sx*sy*v - 2.0*cx*sy,
+                                       cx*v - 2.0*sx,
+                                       -sy*u - sx*cy*v - 2.0*cy*cx ] );
+                let ( colour, hit ) = trace( pos, dir, ground );
+                if hit != 0 && top == THUMB_H {
+                    top = y;
+                }
+                if hit == 1 {
+                    terrain += 1;
+                }
+                let p = ( y*THUMB_W + x )*3;
+                pixels[ p ] = to_u8( colour[ 0 ] );
+                pixels[ p+1 ] = to_u8( colour[ 1 ] );
+                pixels[ p+2 ] = to_u8( colour[ 2 ] );
+                y += 1;
+                if y == THUMB_H { break; }
+            }
+            horizon += top as f32/( THUMB_H*THUMB_W ) as f32;
+            x += 1;
+            if x == THUMB_W { break; }
+        }
+
+        let coverage = terrain as f32/( THUMB_W*THUMB_H ) as f32;
+        let mut coverage_score = 1.0 - ( coverage-COVERAGE_GOAL )*( coverage-COVERAGE_GOAL )/( COVERAGE_GOAL*COVERAGE_GOAL );
+        if coverage_score < 0.0 { coverage_score = 0.0; }
+        let mut horizon_score = 1.0 - ( horizon-HORIZON_GOAL )*( horizon-HORIZON_GOAL )/( HORIZON_GOAL*HORIZON_GOAL );
+        if horizon_score < 0.0 { horizon_score = 0.0; }
+        let buried = pos[ 1 ] < ground( pos[ 0 ], pos[ 2 ] ) || pos[ 1 ] < WATER_LEVEL;
+        shots[ idx ] = ( seed, if buried { 0.0 } else { coverage_score*horizon_score }, idx );
+    }
+}
+
+unsafe fn put_u32( pos : usize, val : u32 ) {
+    file_buffer[ pos ] = val as u8;
+    file_buffer[ pos+1 ] = ( val >> 8 ) as u8;
+    file_buffer[ pos+2 ] = ( val >> 16 ) as u8;
+    file_buffer[ pos+3 ] = ( val >> 24 ) as u8;
+}
+
+// Sort the shots and write the contact sheet and list
+pub fn write_sheet() {
+    unsafe{
+        // insertion sort, best first
+        let mut i = 1;
+        while i < SEED_COUNT {
+            let shot = shots[ i ];
+            let mut j = i;
+            while j > 0 && shots[ j-1 ].1 < shot.1 {
+                shots[ j ] = shots[ j-1 ];
+                j -= 1;
+            }
+            shots[ j ] = shot;
+            i += 1;
+        }
+
+        // 24 bit bottom up BMP, grey between the thumbnails
+        let mut p = 0;
+        while p < file_buffer.len() {
+            file_buffer[ p ] = 40;
+            p += 1;
+        }
+        file_buffer[ 0 ] = b'B';
+        file_buffer[ 1 ] = b'M';
+        put_u32( 2, file_buffer.len() as u32 );
+        put_u32( 6, 0 );
+        put_u32( 10, 54 );
+        put_u32( 14, 40 );
+        put_u32( 18, SHEET_W as u32 );
+        put_u32( 22, SHEET_H as u32 );
+        put_u32( 26, 1 | ( 24 << 16 ) );       // planes, bits per pixel
+        put_u32( 30, 0 );                       // uncompressed
+        put_u32( 34, ( SHEET_STRIDE*SHEET_H ) as u32 );
+        put_u32( 38, 2835 );                    // 72 dpi
+        put_u32( 42, 2835 );
+        put_u32( 46, 0 );
+        put_u32( 50, 0 );
+
+        let mut rank = 0;
+        loop {
+            let left = GAP + ( rank%COLUMNS )*( THUMB_W+GAP );
+            let top = GAP + ( rank/COLUMNS )*( THUMB_H+GAP );
+            let pixels = &thumbnails[ shots[ rank ].2*THUMB_W*THUMB_H*3 .. ];
+            let mut y = 0;
+            while y < THUMB_H {
+                let mut x = 0;
+                while x < THUMB_W {
+                    let src = ( y*THUMB_W + x )*3;
+                    let dst = 54 + ( SHEET_H-1-( top+y ) )*SHEET_STRIDE + ( left+x )*3;
+                    file_buffer[ dst ] = pixels[ src+2 ];
+                    file_buffer[ dst+1 ] = pixels[ src+1 ];
+                    file_buffer[ dst+2 ] = pixels[ src ];
+                    x += 1;
+                }
+                y += 1;
+            }
+            rank += 1;
+            if rank == SEED_COUNT { break; }
+        }
+        util::write_file( "seeds.bmp\0", &file_buffer );
+
+        let mut length = 0;
+        let mut rank = 0;
+        loop {
+            let ( seed, score, _ ) = shots[ rank ];
+            length += winapi::um::winuser::wsprintfA( file_buffer.as_mut_ptr().add( length ) as *mut i8, "%d, %d: seed %d score 0.%.3d\r\n\0".as_ptr() as *const i8,
+                ( rank/COLUMNS ) as u32, ( rank%COLUMNS ) as u32, seed, ( score*999.0 ) as u32 ) as usize;
+            rank += 1;
+            if rank == SEED_COUNT { break; }
+        }
+        util::write_file( "seeds.txt\0", &file_buffer[ ..length ] );
+    }
+}
@@ -0,0 +1,87 @@
+// Sphere materials. Every sphere is assigned one of MATERIALS and eases into it over a few frames when
+// the SEQUENCE changes it. Uses the same groups as the choreography
+use super::choreo;
+use super::choreo::GROUPS;
+use super::intro::{MAX_SPHERES, FP_0_02, FP_1_32};
+
+#[derive(Clone, Copy)]
+pub struct SphereMaterial {
+    pub colour : [ f32; 3 ],
+    // below 4, the emissive is packed into the multiples of 4 above it in the sphere texture
+    pub refractive_index : f32,
+    // kept squared as the shader wants it, the original spheres are exactly 18
+    pub radius_squared : f32,
+    // 0..1, light given off on top of the lit colour
+    pub emissive : f32,
+}
+
+// The original look of all the spheres
+const DARK_GLASS : SphereMaterial = SphereMaterial{ colour: [ FP_0_02, FP_0_02, FP_0_02 ], refractive_index: FP_1_32, radius_squared: 18.0, emissive: 0.0 };
+
+// Materials by the number used in the SEQUENCE
+static MATERIALS : [ SphereMaterial; 8 ] = [
+    DARK_GLASS,
+    SphereMaterial{ colour: [ 0.01, 0.01, 0.012 ], refractive_index: 1.52, radius_squared: 18.0, emissive: 0.0 },    // clear glass
+    SphereMaterial{ colour: [ 0.35, 0.25, 0.05 ], refractive_index: 1.9, radius_squared: 18.0, emissive: 0.0 },      // gold
+    SphereMaterial{ colour: [ 0.4, 0.4, 0.38 ], refractive_index: 1.05, radius_squared: 12.25, emissive: 0.0 },      // chalk
+    SphereMaterial{ colour: [ 0.6, 0.15, 0.02 ], refractive_index: 1.3, radius_squared: 9.0, emissive: 0.8 },        // ember
+    SphereMaterial{ colour: [ 0.1, 0.3, 0.6 ], refractive_index: 1.3, radius_squared: 9.0, emissive: 0.6 },          // ice glow
+    SphereMaterial{ colour: [ 0.02, 0.03, 0.04 ], refractive_index: 1.45, radius_squared: 49.0, emissive: 0.0 },     // big glass
+    SphereMaterial{ colour: [ 0.3, 0.3, 0.32 ], refractive_index: 1.6, radius_squared: 4.0, emissive: 0.0 },         // pearl
+];
+
+// Fraction of the way to the assigned material covered each frame
+const MATERIAL_EASE : f32 = 0.05;
+
+static mut assigned : [ u8; MAX_SPHERES ] = [ 0; MAX_SPHERES ];
+static mut current : [ SphereMaterial; MAX_SPHERES ] = [ DARK_GLASS; MAX_SPHERES ];
+
+// Give the spheres of a group ( every group if group >= GROUPS ) materials a and b of MATERIALS in turn. Numbers
+// past the end of MATERIALS give the last one
+pub fn set_materials( group : u32, a : u32, b : u32 ) {
+    let last = ( MATERIALS.len()-1 ) as u32;
+    let a = if a < last { a } else { last };
+    let b = if b < last { b } else { last };
+    unsafe{
+        let mut g = 0;
+        loop {
+            if group as usize >= GROUPS || group as usize == g {
+                let mut idx = choreo::group_first( g );
+                while idx < choreo::group_first( g+1 ) {
+                    assigned[ idx ] = ( if idx%2 == 0 { a } else { b } ) as u8;
+                    idx += 1;
+                }
+            }
+            g += 1;
+            if g == GROUPS { break; }
+        }
+    }
+}
+
+// Ease the materials on by a frame and write them to the sphere data. Covers every sphere up to MAX_SPHERES so
+// spheres added to the scene are ready. spheres is the sphere texture data, the radius squared goes in the w of the first vec4 and ( colour, packed refractive index and emissive ) in the second
+pub fn update( spheres : &mut [ [ f32; 4 ] ] ) {
+    unsafe{
+        let mut idx = 0;
+        loop {
+            let goal = &MATERIALS[ assigned[ idx ] as usize ];
+            let m = &mut current[ idx ];
+            let mut c = 0;
+            while c < 3 {
+                m.colour[ c ] += ( goal.colour[ c ]-m.colour[ c ] )*MATERIAL_EASE;
+                c += 1;
+            }
+            m.refractive_index += ( goal.refractive_index-m.refractive_index )*MATERIAL_EASE;
+            m.radius_squared += ( goal.radius_squared-m.radius_squared )*MATERIAL_EASE;
+            m.emissive += ( goal.emissive-m.emissive )*MATERIAL_EASE;
+
+            spheres[ idx*2 ][ 3 ] = m.radius_squared;
+            spheres[ idx*2+1 ][ 0 ] = m.colour[ 0 ];
+            spheres[ idx*2+1 ][ 1 ] = m.colour[ 1 ];
+            spheres[ idx*2+1 ][ 2 ] = m.colour[ 2 ];
+            spheres[ idx*2+1 ][ 3 ] = m.refractive_index + ( ( m.emissive*255.0 ) as u32 * 4 ) as f32;
+            idx += 1;
+            if idx == MAX_SPHERES { break; }
+        }
+    }
+}
@@ -0,0 +1,657 @@
+use super::random;
+use core::arch::x86;
+
+// Cells along each side of the terrain. The world is always 512 units across, the shader picks up the
+// resolution from the size of the terrain texture
+#[cfg(not(any(feature = "terrain_256", feature = "terrain_1024")))]
+pub const TERRAIN_SIZE : usize = 512;
+#[cfg(feature = "terrain_256")]
+pub const TERRAIN_SIZE : usize = 256;
+#[cfg(feature = "terrain_1024")]
+pub const TERRAIN_SIZE : usize = 1024;
+#[cfg(all(feature = "terrain_256", feature = "terrain_1024"))]
+compile_error!( "terrain_256 and terrain_1024 are mutually exclusive" );
+
+// RGBA heightfield as uploaded to the terrain texture. Height is in channel 0, 0..1 maps to -12.1..47.9 in get_height
+pub type Heightfield = [ f32; TERRAIN_SIZE*TERRAIN_SIZE*4 ];
+
+pub trait TerrainGenerator {
+    // Accumulate the generated heights into channel 0 of an empty heightfield
+    fn generate( &self, terrain : &mut Heightfield );
+
+    // The straightforward version of generate that terrain_bench times it against and checks it with. Only
+    // generators with a separate fast version need to override it
+    #[cfg(feature = "terrain_bench")]
+    fn generate_reference( &self, terrain : &mut Heightfield ) {
+        self.generate( terrain );
+    }
+
+    // Fill in the material ( channel 1 ) and rockiness ( channel 2 ) read by the shader from the final heights
+    fn classify( &self, terrain : &mut Heightfield ) {
+        classify_materials( terrain );
+    }
+}
+
+// Generators that can produce the height of any cell of an unbounded world. Used for the streamed terrain
+pub trait TileGenerator {
+    fn height( &self, x : i32, z : i32 ) -> f32;
+}
+
+// Fill the whole heightfield from the cells 0..TERRAIN_SIZE of a tile generator
+fn generate_from_tiles( generator : &dyn TileGenerator, terrain : &mut Heightfield ) {
+    let mut z = 0;
+    loop{
+        let mut x = 0;
+        loop{
+            unsafe{ *terrain.get_unchecked_mut( cell( x, z ) ) += generator.height( x as i32, z as i32 ); }
+            x += 1;
+            if x == TERRAIN_SIZE { break; }
+        }
+        z += 1;
+        if z == TERRAIN_SIZE { break; }
+    }
+}
+
+pub const MATERIAL_ROCK : f32 = 0.0;
+pub const MATERIAL_SAND : f32 = 1.0;
+pub const MATERIAL_GRASS : f32 = 2.0;
+pub const MATERIAL_SNOW : f32 = 3.0;
+
+// Sand just above the water plane at -0.5, snow above 40
+const SAND_HEIGHT : f32 = ( 1.5+12.1 )/60.0;
+const SNOW_HEIGHT : f32 = ( 40.0+12.1 )/60.0;
+// Height difference to the neighbours ( in world units per 512th of the world ) at which the top turns fully to rock
+const ROCK_SLOPE : f32 = 6.0;
+
+// Index of the height of cell x,z
+#[inline(always)]
+fn cell( x : usize, z : usize ) -> usize {
+    ( z*TERRAIN_SIZE + x )*4
+}
+
+// The original terrain. Drop some lumps and randomly aggregate points around them
+pub struct LumpCharge {
+    pub seed : u32,
+    // at most MAX_LUMPS, more are left out
+    pub num_lumps : usize,
+    pub samples : usize,
+}
+
+pub const MAX_LUMPS : usize = 64;
+
+#[cfg(feature = "threads")]
+const TERRAIN_THREADS : usize = 8;
+// Charges worked out in one go, more samples are done in several batches
+#[cfg(feature = "threads")]
+const MAX_SAMPLES : usize = 3 << 20;
+#[cfg(feature = "threads")]
+static mut sample_charges : [ f32; MAX_SAMPLES ] = [ 0.0; MAX_SAMPLES ];
+
+// Work for one thread. Charges for samples first..last
+#[cfg(feature = "threads")]
+struct ChargeJob {
+    generator : *const LumpCharge,
+    lumps : *const [[f32;4];MAX_LUMPS],
+    rng : random::Rng,
+    first : usize,
+    last : usize,
+}
+
+#[cfg(feature = "threads")]
+unsafe extern "system" fn charge_thread( param : winapi::shared::minwindef::LPVOID ) -> winapi::shared::minwindef::DWORD {
+    let job = &mut *( param as *mut ChargeJob );
+    let mut i = job.first;
+    while i < job.last {
+        let mut x = [ 0f32; 4 ];
+        let mut z = [ 0f32; 4 ];
+        let mut lane = 0;
+        while lane < 4 && i+lane < job.last {
+            LumpCharge::next_sample( &mut job.rng, &mut x[ lane ], &mut z[ lane ] );
+            lane += 1;
+        }
+        let charges = ( *job.generator ).charges4( &*job.lumps, &x, &z );
+        let mut l = 0;
+        while l < lane {
+            *sample_charges.get_unchecked_mut( i+l ) = charges[ l ];
+            l += 1;
+        }
+        i += 4;
+    }
+    0
+}
+
+impl LumpCharge {
+    // num_lumps kept within the lump table
+    fn lump_count( &self ) -> usize {
+        if self.num_lumps > MAX_LUMPS { MAX_LUMPS } else { self.num_lumps }
+    }
+
+    fn place_lumps( &self, rng_terrain : &mut random::Rng, lumps : &mut [[f32;4];MAX_LUMPS] ) {
+        let count = self.lump_count();
+        unsafe{
+            let mut nl = 0;
+            while nl < count {
+                // do not put the lumps too close to the edges to avoid ugly discontinuities
+                let lmp = lumps.get_unchecked_mut(nl);
+                let x = rng_terrain.next_f32();
+                let z = rng_terrain.next_f32();
+                lmp[ 0 ] = (x+0.1)*0.8;
+                lmp[ 1 ] = (rng_terrain.next_f32()+0.1)*0.8;
+                lmp[ 2 ] = (z+0.1)*0.8;
+                nl += 1;
+            }
+        }
+    }
+
+    // Each sample uses three random numbers, the third is the unused height
+    #[inline(always)]
+    fn next_sample( rng_terrain : &mut random::Rng, x : &mut f32, z : &mut f32 ) {
+        *x = rng_terrain.next_f32();
+        *z = rng_terrain.next_f32();
+        rng_terrain.next_f32();
+    }
+
+    #[inline(always)]
+    fn add_charge( terrain : &mut Heightfield, x : f32, z : f32, charge : f32 ) {
+        let pos = cell( (x*TERRAIN_SIZE as f32) as usize, (z*TERRAIN_SIZE as f32) as usize );
+        unsafe{
+            *terrain.get_unchecked_mut( pos ) += charge;
+            if *terrain.get_unchecked( pos ) > 1.0  {
+                *terrain.get_unchecked_mut( pos ) = 1.0
+            }
+        }
+    }
+
+    // Charge of four samples at once. Vectorised across the samples rather than the lumps so that every lane
+    // adds up the lumps in the same order as the scalar version and the result is bit identical
+    fn charges4( &self, lumps : &[[f32;4];MAX_LUMPS], x : &[f32;4], z : &[f32;4] ) -> [f32;4] {
+        let mut result = [ 0f32; 4 ];
+        let count = self.lump_count();
+        unsafe{
+            let xs = x86::_mm_loadu_ps( x.as_ptr() );
+            let zs = x86::_mm_loadu_ps( z.as_ptr() );
+            let mut charge = x86::_mm_setzero_ps();
+            let mut nl = 0;
+            while nl < count {
+                let lmp = lumps.get_unchecked(nl);
+                let dx = x86::_mm_sub_ps( xs, x86::_mm_set1_ps( lmp[ 0 ] ) );
+                let dz = x86::_mm_sub_ps( zs, x86::_mm_set1_ps( lmp[ 2 ] ) );
+                let dist = x86::_mm_add_ps( x86::_mm_mul_ps( dx, dx ), x86::_mm_mul_ps( dz, dz ) );
+                charge = x86::_mm_add_ps( charge, x86::_mm_div_ps( x86::_mm_set1_ps( lmp[1]*0.0001 ), dist ) );
+                nl += 1;
+            }
+            x86::_mm_storeu_ps( result.as_mut_ptr(), charge );
+        }
+        result
+    }
+}
+
+impl TerrainGenerator for LumpCharge {
+    // The original one sample at a time version. Kept as the reference the fast version is checked against
+    #[cfg(feature = "terrain_bench")]
+    fn generate_reference( &self, terrain : &mut Heightfield ) {
+        let mut rng_terrain : random::Rng = random::Rng{seed: core::num::Wrapping(self.seed)};
+        let mut lumps : [[f32;4];MAX_LUMPS] = [[0f32;4];MAX_LUMPS];
+        self.place_lumps( &mut rng_terrain, &mut lumps );
+        let count = self.lump_count();
+
+        unsafe{
+            let  mut i = 0;
+            while i < self.samples {
+                let mut x = 0.0;
+                let mut z = 0.0;
+                LumpCharge::next_sample( &mut rng_terrain, &mut x, &mut z );
+
+                let mut charge = 0.0;
+                let mut nl = 0;
+                while nl < count {
+                    let lmp = lumps.get_unchecked(nl);
+                    let dist = (x-lmp[0])*(x-lmp[0]) + (z-lmp[2])*(z-lmp[2]);
+                    charge += lmp[1]*0.0001/dist;
+                    nl += 1;
+                }
+                LumpCharge::add_charge( terrain, x, z, charge );
+                i += 1;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "threads"))]
+    fn generate( &self, terrain : &mut Heightfield ) {
+        let mut rng_terrain : random::Rng = random::Rng{seed: core::num::Wrapping(self.seed)};
+        let mut lumps : [[f32;4];MAX_LUMPS] = [[0f32;4];MAX_LUMPS];
+        self.place_lumps( &mut rng_terrain, &mut lumps );
+
+        let mut i = 0;
+        while i < self.samples {
+            let mut x = [ 0f32; 4 ];
+            let mut z = [ 0f32; 4 ];
+            let mut lane = 0;
+            while lane < 4 && i+lane < self.samples {
+                LumpCharge::next_sample( &mut rng_terrain, &mut x[ lane ], &mut z[ lane ] );
+                lane += 1;
+            }
+            let charges = self.charges4( &lumps, &x, &z );
+            // Samples can land on the same cell so the charges are added in the original order
+            let mut l = 0;
+            while l < lane {
+                LumpCharge::add_charge( terrain, x[ l ], z[ l ], charges[ l ] );
+                l += 1;
+            }
+            i += 4;
+        }
+    }
+
+    // Charges are worked out in parallel. Adding them to the terrain is cheap and done in order afterwards
+    #[cfg(feature = "threads")]
+    fn generate( &self, terrain : &mut Heightfield ) {
+        let mut rng_terrain : random::Rng = random::Rng{seed: core::num::Wrapping(self.seed)};
+        let mut lumps : [[f32;4];MAX_LUMPS] = [[0f32;4];MAX_LUMPS];
+        self.place_lumps( &mut rng_terrain, &mut lumps );
+
+        let mut done = 0;
+        while done < self.samples {
+            let samples = if self.samples-done > MAX_SAMPLES { MAX_SAMPLES } else { self.samples-done };
+            self.generate_batch( &lumps, &mut rng_terrain, samples, terrain );
+            done += samples;
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+impl LumpCharge {
+    // The next samples samples, up to MAX_SAMPLES, on TERRAIN_THREADS threads. rng_terrain is left after them
+    fn generate_batch( &self, lumps : &[[f32;4];MAX_LUMPS], rng_terrain : &mut random::Rng, samples : usize, terrain : &mut Heightfield ) {
+        let per_thread = ( ( samples + TERRAIN_THREADS - 1 ) / TERRAIN_THREADS + 3 ) & !3;
+        unsafe{
+            let mut jobs : [ ChargeJob; TERRAIN_THREADS ] = core::mem::zeroed();
+            let mut threads : [ winapi::um::winnt::HANDLE; TERRAIN_THREADS ] = [ 0 as winapi::um::winnt::HANDLE; TERRAIN_THREADS ];
+            let mut t = 0;
+            while t < TERRAIN_THREADS {
+                let job = jobs.get_unchecked_mut( t );
+                job.generator = self;
+                job.lumps = lumps;
+                job.first = t*per_thread;
+                job.last = if job.first + per_thread > samples { samples } else { job.first + per_thread };
+                if job.first > samples {
+                    job.first = samples;
+                }
+                job.rng = random::Rng{ seed: rng_terrain.seed };
+                job.rng.skip( ( job.first*3 ) as u32 );
+                threads[ t ] = winapi::um::processthreadsapi::CreateThread( 0 as *mut _, 0, Some( charge_thread ),
+                                job as *mut ChargeJob as winapi::shared::minwindef::LPVOID, 0, 0 as *mut _ );
+                t += 1;
+            }
+            winapi::um::synchapi::WaitForMultipleObjects( TERRAIN_THREADS as u32, threads.as_ptr(), 1, winapi::um::winbase::INFINITE );
+            t = 0;
+            while t < TERRAIN_THREADS {
+                winapi::um::handleapi::CloseHandle( threads[ t ] );
+                t += 1;
+            }
+
+            let mut i = 0;
+            while i < samples {
+                let mut x = 0.0;
+                let mut z = 0.0;
+                LumpCharge::next_sample( rng_terrain, &mut x, &mut z );
+                LumpCharge::add_charge( terrain, x, z, *sample_charges.get_unchecked( i ) );
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "terrain_bench")]
+static mut reference_terrain : Heightfield = [ 0.0; TERRAIN_SIZE*TERRAIN_SIZE*4 ];
+#[cfg(feature = "terrain_bench")]
+static mut fast_terrain : Heightfield = [ 0.0; TERRAIN_SIZE*TERRAIN_SIZE*4 ];
+
+#[cfg(feature = "terrain_bench")]
+pub fn milliseconds() -> f32 {
+    unsafe{
+        let mut frequency : winapi::um::winnt::LARGE_INTEGER = core::mem::zeroed();
+        let mut counter : winapi::um::winnt::LARGE_INTEGER = core::mem::zeroed();
+        winapi::um::profileapi::QueryPerformanceFrequency( &mut frequency );
+        winapi::um::profileapi::QueryPerformanceCounter( &mut counter );
+        ( *counter.QuadPart() as f64 * 1000.0 / *frequency.QuadPart() as f64 ) as f32
+    }
+}
+
+// Time the reference and the fast version of a generator and check that they produce exactly the same terrain
+#[cfg(feature = "terrain_bench")]
+pub fn benchmark( generator : &dyn TerrainGenerator ) {
+    unsafe{
+        let mut i = 0;
+        while i < TERRAIN_SIZE*TERRAIN_SIZE*4 {
+            reference_terrain[ i ] = 0.0;
+            fast_terrain[ i ] = 0.0;
+            i += 1;
+        }
+
+        let start = milliseconds();
+        generator.generate_reference( &mut reference_terrain );
+        let reference_done = milliseconds();
+        generator.generate( &mut fast_terrain );
+        let fast_done = milliseconds();
+        super::log!( "Terrain ms reference, fast: ", reference_done-start, fast_done-reference_done );
+
+        generator.classify( &mut reference_terrain );
+        generator.classify( &mut fast_terrain );
+
+        i = 0;
+        while i < TERRAIN_SIZE*TERRAIN_SIZE*4 {
+            if reference_terrain[ i ].to_bits() != fast_terrain[ i ].to_bits() {
+                let xz = i/4;
+                super::log!( "Terrain mismatch at: ", ( xz % TERRAIN_SIZE ) as f32, ( xz / TERRAIN_SIZE ) as f32, ( i % 4 ) as f32 );
+                super::show_error( "Fast terrain differs from the reference\0".as_ptr() as *const i8 );
+                return;
+            }
+            i += 1;
+        }
+    }
+}
+
+// Material and rockiness of a cell from its height and the heights of the cells to the right and below.
+// The texture channels are normalised so the material is returned as index / 3
+pub fn classify( height : f32, right : f32, down : f32 ) -> ( f32, f32 ) {
+    let slope = ( abs( right-height ) + abs( down-height ) )*60.0*TERRAIN_SIZE as f32/512.0;
+    let material = if height < SAND_HEIGHT {
+        MATERIAL_SAND
+    } else if height > SNOW_HEIGHT {
+        MATERIAL_SNOW
+    } else if slope < ROCK_SLOPE*0.5 {
+        MATERIAL_GRASS
+    } else {
+        MATERIAL_ROCK
+    };
+    let rockiness = slope / ROCK_SLOPE;
+    ( material / 3.0, if rockiness > 1.0 { 1.0 } else { rockiness } )
+}
+
+// Classify the cells by height and slope
+pub fn classify_materials( terrain : &mut Heightfield ) {
+    let mut z = 0;
+    loop{
+        let mut x = 0;
+        loop{
+            unsafe{
+                let height = *terrain.get_unchecked( cell( x, z ) );
+                let right = *terrain.get_unchecked( cell( if x < TERRAIN_SIZE-1 { x+1 } else { x }, z ) );
+                let down = *terrain.get_unchecked( cell( x, if z < TERRAIN_SIZE-1 { z+1 } else { z } ) );
+                let ( material, rockiness ) = classify( height, right, down );
+                *terrain.get_unchecked_mut( cell( x, z )+1 ) = material;
+                *terrain.get_unchecked_mut( cell( x, z )+2 ) = rockiness;
+            }
+            x += 1;
+            if x == TERRAIN_SIZE { break; }
+        }
+        z += 1;
+        if z == TERRAIN_SIZE { break; }
+    }
+}
+
+#[inline(always)]
+pub fn floor( v : f32 ) -> i32 {
+    let i = v as i32;
+    if i as f32 > v { i-1 } else { i }
+}
+
+#[inline(always)]
+fn fade( t : f32 ) -> f32 {
+    t*t*t*(t*(t*6.0-15.0)+10.0)
+}
+
+#[inline(always)]
+fn lerp( a : f32, b : f32, t : f32 ) -> f32 {
+    a + (b-a)*t
+}
+
+#[inline(always)]
+fn abs( v : f32 ) -> f32 {
+    if v < 0.0 { -v } else { v }
+}
+
+fn hash( seed : u32, x : i32, z : i32 ) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4eb2d) ^ (z as u32).wrapping_mul(0x165667b1);
+    h = (h ^ (h >> 15)).wrapping_mul(0x85ebca6b);
+    h = (h ^ (h >> 13)).wrapping_mul(0xc2b2ae35);
+    h ^ (h >> 16)
+}
+
+// Random value in -1..1 at each lattice point
+fn lattice_value( seed : u32, x : i32, z : i32 ) -> f32 {
+    ( hash( seed, x, z ) & 0xffff ) as f32 / 32767.5 - 1.0
+}
+
+// Dot product of one of 8 unit gradients with the offset from the lattice point
+fn lattice_gradient( seed : u32, x : i32, z : i32, dx : f32, dz : f32 ) -> f32 {
+    match hash( seed, x, z ) & 7 {
+        0 => dx,
+        1 => -dx,
+        2 => dz,
+        3 => -dz,
+        4 => ( dx+dz )*0.7071,
+        5 => ( dx-dz )*0.7071,
+        6 => ( -dx+dz )*0.7071,
+        _ => ( -dx-dz )*0.7071,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoiseBasis {
+    Value,
+    Perlin,
+}
+
+// Single octave of noise in roughly -1..1
+pub fn noise( basis : NoiseBasis, seed : u32, x : f32, z : f32 ) -> f32 {
+    let ix = floor( x );
+    let iz = floor( z );
+    let fx = x - ix as f32;
+    let fz = z - iz as f32;
+    let u = fade( fx );
+    let v = fade( fz );
+    if basis == NoiseBasis::Value {
+        lerp( lerp( lattice_value( seed, ix, iz ), lattice_value( seed, ix+1, iz ), u ),
+              lerp( lattice_value( seed, ix, iz+1 ), lattice_value( seed, ix+1, iz+1 ), u ), v )
+    } else {
+        1.4142*lerp( lerp( lattice_gradient( seed, ix, iz, fx, fz ), lattice_gradient( seed, ix+1, iz, fx-1.0, fz ), u ),
+                     lerp( lattice_gradient( seed, ix, iz+1, fx, fz-1.0 ), lattice_gradient( seed, ix+1, iz+1, fx-1.0, fz-1.0 ), u ), v )
+    }
+}
+
+// Fractal brownian motion, the sum of octaves of noise at doubling frequencies
+pub struct Fbm {
+    pub seed : u32,
+    pub basis : NoiseBasis,
+    pub octaves : u32,
+    // features across the whole map for the first octave
+    pub frequency : f32,
+    // amplitude multiplier from one octave to the next
+    pub gain : f32,
+    // heights are base..base+height
+    pub base : f32,
+    pub height : f32,
+}
+
+impl TileGenerator for Fbm {
+    fn height( &self, x : i32, z : i32 ) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency / TERRAIN_SIZE as f32;
+        let mut o = 0;
+        while o < self.octaves {
+            sum += amplitude*noise( self.basis, self.seed.wrapping_add(o), x as f32*frequency, z as f32*frequency );
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= 2.0;
+            o += 1;
+        }
+        // no octaves is flat
+        let n = if total > 0.0 { sum/total } else { 0.0 };
+        self.base + self.height*( n*0.5+0.5 )
+    }
+}
+
+impl TerrainGenerator for Fbm {
+    fn generate( &self, terrain : &mut Heightfield ) {
+        generate_from_tiles( self, terrain );
+    }
+}
+
+// Ridged multifractal ( Musgrave ). Sharp ridges where the noise crosses zero, rougher detail on the ridges than in the valleys
+pub struct RidgedMultifractal {
+    pub seed : u32,
+    pub octaves : u32,
+    pub frequency : f32,
+    pub gain : f32,
+    // ridge sharpness, usually around 1.0
+    pub offset : f32,
+    pub base : f32,
+    pub height : f32,
+}
+
+impl TileGenerator for RidgedMultifractal {
+    fn height( &self, x : i32, z : i32 ) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut weight = 1.0;
+        let mut frequency = self.frequency / TERRAIN_SIZE as f32;
+        let mut o = 0;
+        while o < self.octaves {
+            let mut signal = self.offset - abs( noise( NoiseBasis::Perlin, self.seed.wrapping_add(o), x as f32*frequency, z as f32*frequency ) );
+            signal *= signal*weight;
+            weight = signal*self.gain;
+            if weight > 1.0 { weight = 1.0; }
+            if weight < 0.0 { weight = 0.0; }
+            sum += signal*amplitude;
+            total += self.offset*self.offset*amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+            o += 1;
+        }
+        self.base + if total > 0.0 { self.height*sum/total } else { 0.0 }
+    }
+}
+
+impl TerrainGenerator for RidgedMultifractal {
+    fn generate( &self, terrain : &mut Heightfield ) {
+        generate_from_tiles( self, terrain );
+    }
+}
+
+// Runs water droplets down the terrain produced by another generator. Droplets pick up sediment on the
+// way down and deposit it when they slow down, carving gullies and filling valleys
+pub struct HydraulicErosion<'a> {
+    pub base : &'a ( dyn TerrainGenerator + Sync ),
+    pub seed : u32,
+    pub droplets : u32,
+    pub lifetime : u32,
+    // how much the droplet keeps its direction instead of following the slope
+    pub inertia : f32,
+    pub capacity : f32,
+    pub erode_rate : f32,
+    pub deposit_rate : f32,
+    pub evaporate_rate : f32,
+}
+
+// Bilinearly interpolated height and its gradient at x,z
+fn height_gradient( terrain : &Heightfield, x : f32, z : f32 ) -> ( f32, f32, f32 ) {
+    let ix = x as usize;
+    let iz = z as usize;
+    let u = x - ix as f32;
+    let v = z - iz as f32;
+    unsafe{
+        let h00 = *terrain.get_unchecked( cell( ix, iz ) );
+        let h10 = *terrain.get_unchecked( cell( ix+1, iz ) );
+        let h01 = *terrain.get_unchecked( cell( ix, iz+1 ) );
+        let h11 = *terrain.get_unchecked( cell( ix+1, iz+1 ) );
+        ( lerp( lerp( h00, h10, u ), lerp( h01, h11, u ), v ),
+          lerp( h10-h00, h11-h01, v ),
+          lerp( h01-h00, h11-h10, u ) )
+    }
+}
+
+// Spread amount over the four cells around x,z
+fn add_bilinear( terrain : &mut Heightfield, x : f32, z : f32, amount : f32 ) {
+    let ix = x as usize;
+    let iz = z as usize;
+    let u = x - ix as f32;
+    let v = z - iz as f32;
+    unsafe{
+        *terrain.get_unchecked_mut( cell( ix, iz ) ) += amount*(1.0-u)*(1.0-v);
+        *terrain.get_unchecked_mut( cell( ix+1, iz ) ) += amount*u*(1.0-v);
+        *terrain.get_unchecked_mut( cell( ix, iz+1 ) ) += amount*(1.0-u)*v;
+        *terrain.get_unchecked_mut( cell( ix+1, iz+1 ) ) += amount*u*v;
+    }
+}
+
+impl<'a> TerrainGenerator for HydraulicErosion<'a> {
+    fn generate( &self, terrain : &mut Heightfield ) {
+        self.base.generate( terrain );
+
+        let mut rng_drops : random::Rng = random::Rng{seed: core::num::Wrapping(self.seed)};
+        let mut d = 0;
+        while d < self.droplets {
+            let mut x = 1.0 + rng_drops.next_f32()*( TERRAIN_SIZE-3 ) as f32;
+            let mut z = 1.0 + rng_drops.next_f32()*( TERRAIN_SIZE-3 ) as f32;
+            let mut dir_x = 0.0;
+            let mut dir_z = 0.0;
+            let mut speed = 1.0;
+            let mut water = 1.0;
+            let mut sediment = 0.0;
+
+            let mut step = 0;
+            while step < self.lifetime {
+                let ( height, grad_x, grad_z ) = height_gradient( terrain, x, z );
+                dir_x = dir_x*self.inertia - grad_x*(1.0-self.inertia);
+                dir_z = dir_z*self.inertia - grad_z*(1.0-self.inertia);
+                let len = unsafe{ core::intrinsics::sqrtf32( dir_x*dir_x + dir_z*dir_z ) };
+                if len == 0.0 {
+                    break;
+                }
+                dir_x /= len;
+                dir_z /= len;
+                let new_x = x + dir_x;
+                let new_z = z + dir_z;
+                if new_x < 0.0 || new_x >= ( TERRAIN_SIZE-1 ) as f32 || new_z < 0.0 || new_z >= ( TERRAIN_SIZE-1 ) as f32 {
+                    break;
+                }
+
+                let ( new_height, _, _ ) = height_gradient( terrain, new_x, new_z );
+                let delta = new_height - height;
+                let mut capacity = -delta*speed*water*self.capacity;
+                if capacity < 0.0001 {
+                    capacity = 0.0001;
+                }
+
+                if delta > 0.0 || sediment > capacity {
+                    // going uphill fills the pit behind, otherwise drop the excess
+                    let mut amount = ( sediment-capacity )*self.deposit_rate;
+                    if delta > 0.0 {
+                        amount = if delta < sediment { delta } else { sediment };
+                    }
+                    sediment -= amount;
+                    add_bilinear( terrain, x, z, amount );
+                } else {
+                    let mut amount = ( capacity-sediment )*self.erode_rate;
+                    if amount > -delta {
+                        amount = -delta;
+                    }
+                    sediment += amount;
+                    add_bilinear( terrain, x, z, -amount );
+                }
+
+                speed = speed*speed - delta;
+                speed = if speed > 0.0 { unsafe{ core::intrinsics::sqrtf32( speed ) } } else { 0.0 };
+                water *= 1.0-self.evaporate_rate;
+                x = new_x;
+                z = new_z;
+                step += 1;
+            }
+            d += 1;
+        }
+    }
+}
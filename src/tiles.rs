@@ -0,0 +1,144 @@
+// Streaming of an unbounded terrain around the camera. The terrain texture holds a window of
+// WINDOW_TILES x WINDOW_TILES tiles. World cell x,z is stored at x,z modulo TERRAIN_SIZE so with REPEAT
+// wrapping the shader addresses it directly and moving the window only replaces the tiles that left it
+use super::gl;
+use super::gl::CVoid;
+use super::terrain;
+use super::terrain::TileGenerator;
+use super::terrain::TERRAIN_SIZE;
+
+const WINDOW_TILES : i32 = 4;
+const TILE_CELLS : i32 = TERRAIN_SIZE as i32 / WINDOW_TILES;
+
+// World tile held by each slot of the texture
+static mut tile_coords : [ ( i32, i32 ); ( WINDOW_TILES*WINDOW_TILES ) as usize ] = [ ( i32::MIN, i32::MIN ); ( WINDOW_TILES*WINDOW_TILES ) as usize ];
+// First tile of the last window that was complete. Far away so the first update builds everything
+static mut window : ( i32, i32 ) = ( i32::MIN/2, i32::MIN/2 );
+// Heights of the tile being built plus one more row and column for the slopes
+static mut tile_heights : [ f32; ( TILE_CELLS as usize+1 )*( TILE_CELLS as usize+1 ) ] = [ 0.0; ( TILE_CELLS as usize+1 )*( TILE_CELLS as usize+1 ) ];
+
+fn slot( tx : i32, tz : i32 ) -> usize {
+    ( tz.rem_euclid( WINDOW_TILES )*WINDOW_TILES + tx.rem_euclid( WINDOW_TILES ) ) as usize
+}
+
+fn is_stale( tx : i32, tz : i32 ) -> bool {
+    unsafe{ *tile_coords.get_unchecked( slot( tx, tz ) ) != ( tx, tz ) }
+}
+
+// First tile of the window starting at tile x,z that is not in the texture yet
+fn next_stale( x : i32, z : i32 ) -> Option<( i32, i32 )> {
+    let mut tz = z;
+    loop{
+        let mut tx = x;
+        loop{
+            if is_stale( tx, tz ) {
+                return Some( ( tx, tz ) );
+            }
+            tx += 1;
+            if tx == x+WINDOW_TILES { break; }
+        }
+        tz += 1;
+        if tz == z+WINDOW_TILES { break; }
+    }
+    None
+}
+
+// Generate and classify world tile tx,tz into its slot of the heightfield and upload that part of the texture
+fn build_tile( generator : &dyn TileGenerator, terrain : &mut terrain::Heightfield, tx : i32, tz : i32 ) {
+    let size = TILE_CELLS as usize;
+    unsafe{
+        let mut z = 0;
+        loop{
+            let mut x = 0;
+            loop{
+                *tile_heights.get_unchecked_mut( z*( size+1 ) + x ) = generator.height( tx*TILE_CELLS + x as i32, tz*TILE_CELLS + z as i32 );
+                x += 1;
+                if x == size+1 { break; }
+            }
+            z += 1;
+            if z == size+1 { break; }
+        }
+
+        let start_x = ( tx.rem_euclid( WINDOW_TILES )*TILE_CELLS ) as usize;
+        let start_z = ( tz.rem_euclid( WINDOW_TILES )*TILE_CELLS ) as usize;
+        let mut z = 0;
+        loop{
+            let mut x = 0;
+            loop{
+                let height = *tile_heights.get_unchecked( z*( size+1 ) + x );
+                let right = *tile_heights.get_unchecked( z*( size+1 ) + x+1 );
+                let down = *tile_heights.get_unchecked( ( z+1 )*( size+1 ) + x );
+                let ( material, rockiness ) = terrain::classify( height, right, down );
+                let dst = ( ( start_z+z )*TERRAIN_SIZE + start_x+x )*4;
+                *terrain.get_unchecked_mut( dst ) = height;
+                *terrain.get_unchecked_mut( dst+1 ) = material;
+                *terrain.get_unchecked_mut( dst+2 ) = rockiness;
+                x += 1;
+                if x == size { break; }
+            }
+            z += 1;
+            if z == size { break; }
+        }
+        *tile_coords.get_unchecked_mut( slot( tx, tz ) ) = ( tx, tz );
+
+        gl::PixelStorei( gl::UNPACK_ROW_LENGTH, TERRAIN_SIZE as i32 );
+        gl::TexSubImage2D( gl::TEXTURE_2D, 0, start_x as i32, start_z as i32, TILE_CELLS, TILE_CELLS, gl::RGBA, gl::FLOAT,
+            terrain.as_ptr().add( ( start_z*TERRAIN_SIZE + start_x )*4 ) as *const CVoid );
+    }
+}
+
+fn round( v : f32 ) -> i32 {
+    let r = ( v+0.5 ) as i32;
+    if ( r as f32 ) > v+0.5 { r-1 } else { r }
+}
+
+// Move the window so the camera ( at world x,z ) stays near its centre. Builds at most one tile per frame
+// while the window slides, everything at once after a cut. Returns the part of the world that is in the
+// texture as ( min x, min z, max x, max z ) in terrain cells for the shader
+pub fn update( generator : &dyn TileGenerator, terrain : &mut terrain::Heightfield, x : f32, z : f32 ) -> [ f32; 4 ] {
+    let cells = TERRAIN_SIZE as f32/512.0;
+    let target = ( round( x*cells/TILE_CELLS as f32 ) - WINDOW_TILES/2, round( z*cells/TILE_CELLS as f32 ) - WINDOW_TILES/2 );
+    unsafe{
+        // Tiles that are in both the last complete window and the one being built
+        let x0 = if window.0 > target.0 { window.0 } else { target.0 };
+        let z0 = if window.1 > target.1 { window.1 } else { target.1 };
+        let x1 = ( if window.0 < target.0 { window.0 } else { target.0 } ) + WINDOW_TILES;
+        let z1 = ( if window.1 < target.1 { window.1 } else { target.1 } ) + WINDOW_TILES;
+
+        if window != target {
+            // The overlap can only be shown while none of its tiles have been replaced. Otherwise
+            // ( e.g. after a cut ) fill in the whole window straight away
+            let mut usable = x0 < x1 && z0 < z1;
+            let mut tz = z0;
+            while usable && tz < z1 {
+                let mut tx = x0;
+                while tx < x1 {
+                    if is_stale( tx, tz ) {
+                        usable = false;
+                    }
+                    tx += 1;
+                }
+                tz += 1;
+            }
+            loop{
+                match next_stale( target.0, target.1 ) {
+                    Some( ( tx, tz ) ) => {
+                        build_tile( generator, terrain, tx, tz );
+                        if usable { break; }
+                    }
+                    None => {
+                        window = target;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let tile = TILE_CELLS as f32;
+        if window == target {
+            [ target.0 as f32*tile, target.1 as f32*tile, ( target.0+WINDOW_TILES ) as f32*tile, ( target.1+WINDOW_TILES ) as f32*tile ]
+        } else {
+            [ x0 as f32*tile, z0 as f32*tile, x1 as f32*tile, z1 as f32*tile ]
+        }
+    }
+}
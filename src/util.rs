@@ -36,13 +36,18 @@ macro_rules! log {
 
 #[cfg(feature = "logger")]
 pub unsafe fn log0( message : &str ) {
-    let name = "dbg_out.txt\0";
+    append_file( "dbg_out.txt\0", message.as_bytes() );
+}
+
+// Adds src to the end of the file, creating it if needed
+#[cfg(feature = "logger")]
+pub unsafe fn append_file( file_name : &str, src : &[u8] ) {
     let mut out = 0;
 
-    let hFile = CreateFileA( name.as_ptr() as *const i8, FILE_APPEND_DATA, 0, 
+    let hFile = CreateFileA( file_name.as_ptr() as *const i8, FILE_APPEND_DATA, 0, 
                 0 as *mut winapi::um::minwinbase::SECURITY_ATTRIBUTES, OPEN_ALWAYS, FILE_ATTRIBUTE_NORMAL, 
                 0 as *mut winapi::ctypes::c_void );
-    WriteFile( hFile, message.as_ptr() as *const winapi::ctypes::c_void, message.len() as u32, &mut out, 
+    WriteFile( hFile, src.as_ptr() as *const winapi::ctypes::c_void, src.len() as u32, &mut out, 
                 0 as *mut winapi::um::minwinbase::OVERLAPPED );
     CloseHandle( hFile );
 }
@@ -61,6 +66,10 @@ pub fn get_c_string_length( buffer: &[u8]) -> usize {
 
 #[cfg(feature = "logger")]
 pub fn f32_to_text( dest: &mut[u8], value: f32, comma: bool ) -> usize {
+    if value < 0.0 {
+        dest[ 0 ] = '-' as u8;
+        return 1 + f32_to_text( &mut dest[ 1.. ], -value, comma );
+    }
     let int_part = value as u32;
     let frac_part = ((value - int_part as f32)*10000f32 ) as u32; 
     unsafe{ winapi::um::winuser::wsprintfA( dest.as_mut_ptr() as * mut i8, "%d.%.4d\0".as_ptr() as * const i8, int_part, frac_part); }
@@ -106,8 +115,9 @@ pub unsafe fn log3( message : &str, value1: f32, value2: f32, value3: f32 ) {
     log0( core::str::from_utf8_unchecked(&buffer[ 0 .. buffer_text_len ]));
 }
 
+// Returns the number of bytes read, 0 if the file does not exist
 #[cfg(feature = "logger")]
-pub unsafe fn read_file( file_name : &str, dst : &mut [u8] ) {
+pub unsafe fn read_file( file_name : &str, dst : &mut [u8] ) -> usize {
     let name = "dbg_out.txt\0";
     let mut out = 0;
 
@@ -120,4 +130,17 @@ pub unsafe fn read_file( file_name : &str, dst : &mut [u8] ) {
                 0 as *mut winapi::um::minwinbase::OVERLAPPED );
     log!( "Close handle...\n");
     CloseHandle( hFile );
+    out as usize
+}
+
+#[cfg(feature = "logger")]
+pub unsafe fn write_file( file_name : &str, src : &[u8] ) {
+    let mut out = 0;
+
+    let hFile = CreateFileA( file_name.as_ptr() as *const i8, GENERIC_WRITE, 0, 
+                0 as *mut winapi::um::minwinbase::SECURITY_ATTRIBUTES, CREATE_ALWAYS, FILE_ATTRIBUTE_NORMAL, 
+                0 as *mut winapi::ctypes::c_void );
+    WriteFile( hFile, src.as_ptr() as *const winapi::ctypes::c_void, src.len() as u32, &mut out, 
+                0 as *mut winapi::um::minwinbase::OVERLAPPED );
+    CloseHandle( hFile );
 }